@@ -3,14 +3,49 @@
 #![cfg_attr(test, deny(warnings))]
 
 //! `Mount` provides mounting middleware for the Iron framework.
+//!
+//! With the `unicode-normalization` feature enabled, route and request path segments are
+//! converted to Unicode Normalization Form C before they are compared, so that canonically
+//! equivalent paths are routed identically.
 
 extern crate iron;
 extern crate url;
 extern crate sequence_trie;
 extern crate hyper;
 extern crate typemap;
+#[cfg(feature = "unicode-normalization")]
+extern crate unicode_normalization;
 
-pub use mount::{Mount, OriginalUrl};
+pub use mount::{Mount, OriginalUrl, MountedPath, Params, Routes, Iter, MountNode,
+                AlreadyMounted, InvalidRoute, MatchInfo, MountError, MountedHandler, NoMatch,
+                current_prefix, mount_aware_redirect};
+#[doc(hidden)]
+pub use mount::checked_route;
+
+/// Mounts each handler on its route, once every route has been checked for obvious mistakes.
+///
+/// `mount_checked!(mount, "/api" => api, "/web" => web)` checks each route for empty
+/// segments, as in `/api//users`, and for `.` or `..` segments, which are otherwise resolved
+/// silently, and then calls `mount.mount("/api", api)` followed by `mount.mount("/web", web)`.
+/// A trailing slash is allowed. The routes are checked when the macro runs, not when it is
+/// compiled, so the expansion returns a `Result`: an `InvalidRoute` error for the first
+/// mistake found, in which case no handler is mounted, or `Ok(())` once every handler has
+/// been mounted.
+#[macro_export]
+macro_rules! mount_checked {
+    ($mount:expr, $($route:expr => $handler:expr),+ $(,)*) => {{
+        let mount: &mut $crate::Mount = &mut $mount;
+        let routes: &[&str] = &[$($route),+];
+        match routes.iter().map(|route| $crate::checked_route(route)).find(|c| c.is_err()) {
+            Some(Err(err)) => Err(err),
+            _ => {
+                let mut routes = routes.iter();
+                $(mount.mount(*routes.next().unwrap(), $handler);)+
+                Ok(())
+            }
+        }
+    }}
+}
 
 mod mount;
 
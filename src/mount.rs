@@ -1,8 +1,19 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
-use std::path::{Path, Component};
-use iron::{Handler, Response, Request, IronResult, IronError, Url, status};
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use iron::{Handler, Response, Request, IronResult, IronError, Url, headers, status};
+use iron::method::Method;
+use iron::modifiers::Redirect;
 use iron::typemap;
-use sequence_trie::SequenceTrie;
+use sequence_trie::{self, SequenceTrie};
+use url::form_urlencoded;
+use url::percent_encoding::{percent_decode, utf8_percent_encode, DEFAULT_ENCODE_SET,
+                            QUERY_ENCODE_SET};
 use std::fmt;
 
 /// Exposes the original, unmodified path to be stored in `Request::extensions`.
@@ -10,6 +21,96 @@ use std::fmt;
 pub struct OriginalUrl;
 impl typemap::Key for OriginalUrl { type Value = Url; }
 
+/// Exposes the values captured by wildcard segments in the matched route, to be stored in
+/// `Request::extensions`.
+///
+/// A handler mounted on `/users/:id` sees `{"id": "42"}` for a request to `/users/42`. For nested
+/// mounts the params captured by every enclosing mount are included.
+#[derive(Copy, Clone)]
+pub struct Params;
+impl typemap::Key for Params { type Value = HashMap<String, String>; }
+
+/// Exposes the segments of the request path that were stripped by mounting, to be stored in
+/// `Request::extensions`.
+///
+/// For nested mounts this is the combined prefix of every enclosing mount, so a handler
+/// mounted on `/api` within a `Mount` mounted on `/v1` sees `["v1", "api"]`. Empty segments,
+/// from repeated slashes or the trailing slash of a route mounted with `exact_paths`, are
+/// left out.
+#[derive(Copy, Clone)]
+pub struct MountedPath;
+impl typemap::Key for MountedPath { type Value = Vec<String>; }
+
+/// Returns the combined prefix stripped by every mount enclosing the current handler, as
+/// stored under `MountedPath`, or `None` outside of a mount.
+pub fn current_prefix(req: &Request) -> Option<Vec<String>> {
+    req.extensions.get::<MountedPath>().cloned()
+}
+
+/// Builds a `302 Found` redirect to a path relative to the current handler's mount.
+///
+/// `to` is a path as it would appear in a URL, optionally with a query string and a
+/// fragment, such as `/login?next=2#form`. It is put after the prefix stored under
+/// `MountedPath`, so a handler mounted on `/admin` within a `Mount` mounted on `/app`
+/// redirects to `/app/admin/login`. Characters which aren't allowed in a URL, such as
+/// spaces, are percent-encoded, while existing escapes such as `%20` are kept. The scheme
+/// and host are those of the `OriginalUrl`, or of the request's URL outside of a mount.
+pub fn mount_aware_redirect(req: &Request, to: &str) -> Response {
+    let mut url = match req.extensions.get::<OriginalUrl>() {
+        Some(original) => original.clone(),
+        None => req.url.clone()
+    };
+    let (to, fragment) = match to.find('#') {
+        Some(i) => (&to[..i], Some(utf8_percent_encode(&to[i + 1..], QUERY_ENCODE_SET))),
+        None => (to, None)
+    };
+    let (path, query) = match to.find('?') {
+        Some(i) => (&to[..i], Some(utf8_percent_encode(&to[i + 1..], QUERY_ENCODE_SET))),
+        None => (to, None)
+    };
+    let path = if path.starts_with('/') { &path[1..] } else { path };
+
+    url.path = current_prefix(req).unwrap_or_else(Vec::new);
+    url.path.extend(path.split('/').map(|s| utf8_percent_encode(s, DEFAULT_ENCODE_SET)));
+    url.query = query;
+    url.fragment = fragment;
+    Response::with((status::Found, Redirect(url)))
+}
+
+/// Checks a route given to the `mount_checked!` macro, returning it unchanged.
+///
+/// Returns an `InvalidRoute` error if the route is empty or only whitespace, or contains an
+/// empty segment, other than a trailing slash, or a `.` or `..` segment.
+#[doc(hidden)]
+pub fn checked_route(route: &str) -> Result<&str, InvalidRoute> {
+    let invalid = |problem: String| {
+        Err(InvalidRoute { route: route.to_string(), problem: problem })
+    };
+    if route.trim().is_empty() {
+        return invalid("is empty; use \"/\" to mount on the root".to_string());
+    }
+
+    let start = if route.starts_with('/') { 1 } else { 0 };
+    let end = route.len() - (route.len() > start && route.ends_with('/')) as usize;
+    let trimmed = &route[start..end];
+    if trimmed.is_empty() {
+        if route.len() > 1 {
+            return invalid("contains an empty segment".to_string());
+        }
+        return Ok(route);
+    }
+
+    for segment in trimmed.split('/') {
+        if segment.is_empty() {
+            return invalid("contains an empty segment".to_string());
+        }
+        if segment == "." || segment == ".." {
+            return invalid(format!("contains a {:?} segment", segment));
+        }
+    }
+    Ok(route)
+}
+
 /// `Mount` is a simple mounting middleware.
 ///
 /// Mounting allows you to install a handler on a route and have it receive requests as if they
@@ -21,27 +122,396 @@ impl typemap::Key for OriginalUrl { type Value = Url; }
 /// route.
 ///
 /// Mounted handlers may also access the *original* URL by requesting the `OriginalUrl` key
-/// from `Request::extensions`.
+/// from `Request::extensions`, and the prefix they were mounted under with the `MountedPath`
+/// key. The URL is restored after the handler returns without relying on these keys, so a
+/// handler which removes them does no harm. A handler mounted on `/` of the outermost mount
+/// already sees the URL as it was received, so neither key is set for it.
+///
+/// Routes are matched against whole segments of the request path, never part of one, so a
+/// handler mounted on `/foo` matches `/foo` and `/foo/bar` but not `/foobar`.
+///
+/// Request path segments are percent-decoded before matching, so a request for `/%61pi` matches
+/// a handler mounted on `/api`. Routes should therefore be given unencoded. An encoded slash
+/// (`%2F`) does not separate segments, so `/foo%2Fbar` is the single segment `foo/bar`, which
+/// matches neither `/foo` nor `/foo/bar`. Empty segments, from trailing or repeated slashes, are
+/// ignored when matching, so `/api//users/` matches a handler mounted on `/api/users`.
+///
+/// Handlers may also be mounted for a specific host with `Mount::mount_host`, in which case they
+/// are only considered for requests whose `Host` header names that host.
+///
+/// Handlers mounted with `Mount::mount_method` are only considered for requests with that
+/// method.
+///
+/// Handlers mounted with `Mount::mount_accept` are chosen between by the media types the
+/// request's `Accept` header allows.
+///
+/// Requests which don't match any mounted handler are passed to the fallback handler, if one
+/// has been set with `Mount::fallback`, and otherwise result in a `MountError`, whose
+/// response can be customized with `Mount::no_match_response` or `Mount::no_match_with`.
+///
+/// `Mount` is `Send` and `Sync`, so a routing table can be built once and shared between
+/// threads behind an `Arc`. It is also cheap to clone: a clone has its own routing table,
+/// which can be changed without affecting the original, but shares the handlers.
+#[derive(Clone)]
 pub struct Mount {
-    inner: SequenceTrie<String, Match>
+    inner: SequenceTrie<String, Vec<Match>>,
+    fallbacks: Vec<Arc<Handler>>,
+    // The defaults for subtrees, set with `mount_default`, keyed by their folded prefix.
+    defaults: SequenceTrie<String, Arc<Handler>>,
+    no_match: Option<Arc<Fn() -> Response + Send + Sync>>,
+    disabled_status: status::Status,
+    on_overwrite: Option<Arc<Fn(&str) + Send + Sync>>,
+    on_dispatch: Option<Arc<Fn(&[String], &Request) + Send + Sync>>,
+    case_insensitive: bool,
+    // Whether any match was mounted with `mount_ci`, so request paths are also matched folded.
+    folded_routes: bool,
+    redirect_trailing_slash: bool,
+    count_requests: bool,
+    forward_prefix: bool,
+    strip_forwarded_prefix: bool,
+    auto_options: bool,
+    strict: bool,
+    root_empty_path: bool,
+    reset_original_url: bool,
+    exact_paths: bool,
+    global_prefix: Vec<String>,
+    max_depth: Option<usize>,
+    max_segment_len: Option<usize>,
+    limit_status: status::Status
 }
 
+// The handlers mounted on a route. Every value in the trie holds at least one `Match`.
+//
+// The matches on a route are kept ordered by `specificity`, so that for example a handler
+// mounted for a particular method is tried before one mounted for any method.
 struct Match {
-    handler: Box<Handler>,
-    length: usize
+    handler: Arc<Handler>,
+    length: usize,
+    // The route the match is mounted on, set when it is inserted into the trie.
+    route: Vec<String>,
+    // Whether the matched prefix is stripped from the path passed to the handler.
+    strip: bool,
+    // Segments put in place of the stripped prefix, already percent-encoded.
+    rewrite: Vec<String>,
+    // Whether the match only applies when the route is the whole of the request's path.
+    exact: bool,
+    // Whether the route is matched case-insensitively, as mounted with `mount_ci`.
+    fold_case: bool,
+    host: Option<String>,
+    method: Option<Method>,
+    predicate: Option<Arc<Fn(&Request) -> bool + Send + Sync>>,
+    // A key and value which must be present in the request's query string.
+    query: Option<(String, String)>,
+    // The lowercased type and subtype of the media type the match serves, negotiated with
+    // the request's `Accept` header.
+    media_type: Option<(String, String)>,
+    // Matches with a higher priority are preferred among matches on routes of equal length.
+    priority: i32,
+    // Whether requests are dispatched to the handler, rather than refused as unavailable.
+    enabled: bool,
+    // The number of requests dispatched to the handler, if counting is enabled.
+    hits: AtomicU64,
+    // Called when the match is removed from the mount, for handlers mounted with
+    // `mount_lifecycle`.
+    on_unmount: Option<Arc<Fn() + Send + Sync>>
+}
+
+impl Match {
+    fn new(handler: Arc<Handler>, length: usize) -> Match {
+        Match {
+            handler: handler,
+            length: length,
+            route: Vec::new(),
+            strip: true,
+            rewrite: Vec::new(),
+            exact: false,
+            fold_case: false,
+            host: None,
+            method: None,
+            predicate: None,
+            query: None,
+            media_type: None,
+            priority: 0,
+            enabled: true,
+            hits: AtomicU64::new(0),
+            on_unmount: None
+        }
+    }
+
+    // Whether mounting `other` should replace this match rather than sit alongside it.
+    // Predicates can't be compared, so a match with one never replaces another.
+    fn same_slot(&self, other: &Match) -> bool {
+        self.predicate.is_none() && other.predicate.is_none() && self.exact == other.exact &&
+            self.fold_case == other.fold_case && self.host == other.host &&
+            self.method == other.method && self.query == other.query &&
+            self.media_type == other.media_type
+    }
+
+    // Whether this match was mounted with no restrictions on the requests it accepts.
+    fn is_plain(&self) -> bool {
+        !self.exact && !self.fold_case && self.is_unconditional()
+    }
+
+    // Whether this match accepts any request whose path it matches.
+    fn is_unconditional(&self) -> bool {
+        self.host.is_none() && self.accepts_any_for_host()
+    }
+
+    // Whether this match accepts any request for its host whose path it matches.
+    fn accepts_any_for_host(&self) -> bool {
+        self.method.is_none() && self.predicate.is_none() && self.query.is_none() &&
+            self.media_type.is_none()
+    }
+
+    // The number of restrictions on the requests this match accepts.
+    fn specificity(&self) -> usize {
+        self.exact as usize + self.host.iter().count() + self.method.iter().count() +
+            self.predicate.iter().count() + self.query.iter().count() +
+            self.media_type.iter().count()
+    }
+
+    // Whether this match applies to a candidate, given only the request's path.
+    fn accepts_path(&self, candidate: &Candidate) -> bool {
+        (!self.exact || candidate.complete) &&
+            candidate.fold_case.map_or(true, |fold_case| fold_case == self.fold_case)
+    }
+
+    // Tell a handler mounted with `Mount::mount_lifecycle` that it has been removed, unless
+    // a clone of the `Mount` still holds this match and so still routes requests to it.
+    fn unmounted(&self) {
+        if let Some(ref on_unmount) = self.on_unmount {
+            if Arc::strong_count(on_unmount) == 1 {
+                on_unmount();
+            }
+        }
+    }
+
+    // A line describing the requests this match accepts on a route, for `Mount::describe`.
+    fn describe(&self, route: &str) -> String {
+        let method = self.method.as_ref().map_or("*".to_string(), |method| method.to_string());
+        let host = self.host.as_ref().map_or("", |host| &host[..]);
+        let mut line = format!("{} {}{}", method, host, route);
+
+        let mut notes = Vec::new();
+        if self.exact { notes.push("exact".to_string()); }
+        if self.fold_case { notes.push("case-insensitive".to_string()); }
+        if self.predicate.is_some() { notes.push("conditional".to_string()); }
+        if let Some((ref key, ref value)) = self.query {
+            notes.push(format!("query {}={}", key, value));
+        }
+        if let Some((ref top, ref sub)) = self.media_type {
+            notes.push(format!("accept {}/{}", top, sub));
+        }
+        if self.priority != 0 { notes.push(format!("priority {}", self.priority)); }
+        if !self.strip { notes.push("passthrough".to_string()); }
+        if !self.enabled { notes.push("disabled".to_string()); }
+        if !self.rewrite.is_empty() {
+            notes.push(format!("rewrite {}", format_route(&self.rewrite)));
+        }
+        if !notes.is_empty() {
+            line.push_str(&format!(" ({})", notes.join(", ")));
+        }
+        line
+    }
+}
+
+// A clone shares the handler and predicate, and starts from the same request count.
+impl Clone for Match {
+    fn clone(&self) -> Match {
+        Match {
+            handler: self.handler.clone(),
+            length: self.length,
+            route: self.route.clone(),
+            strip: self.strip,
+            rewrite: self.rewrite.clone(),
+            exact: self.exact,
+            fold_case: self.fold_case,
+            host: self.host.clone(),
+            method: self.method.clone(),
+            predicate: self.predicate.clone(),
+            query: self.query.clone(),
+            media_type: self.media_type.clone(),
+            priority: self.priority,
+            enabled: self.enabled,
+            hits: AtomicU64::new(self.hits.load(Ordering::Relaxed)),
+            on_unmount: self.on_unmount.clone()
+        }
+    }
+}
+
+/// A `Handler` which is told when it is mounted and unmounted.
+///
+/// Handlers mounted with `Mount::mount_lifecycle` have `on_mount` called once they are
+/// mounted, and `on_unmount` called once they are removed from the `Mount`, whether by
+/// `Mount::unmount`, `Mount::unmount_subtree` or `Mount::clear`, or by mounting another
+/// handler in their place. This suits handlers which start work tied to their route, such
+/// as a connection pool or a file watcher. Neither is called when the `Mount` is dropped,
+/// and both default to doing nothing.
+///
+/// A clone of the `Mount` shares the handler, so `on_unmount` is only called once the last
+/// of them to hold it removes it, and is called at most once. Removing it from one of several
+/// clones leaves it mounted in the others without telling it.
+pub trait MountedHandler: Handler {
+    /// Called once the handler has been mounted.
+    fn on_mount(&self) {}
+
+    /// Called once the handler has been removed from the `Mount`.
+    fn on_unmount(&self) {}
+}
+
+// Presents a shared handler as an owned one.
+struct Shared(Arc<Handler>);
+
+impl Handler for Shared {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        self.0.handle(req)
+    }
+}
+
+// The matches on a route which matches a request's path, along with the values captured
+// by the route's wildcard segments.
+struct Candidate<'a> {
+    route: Vec<&'a String>,
+    // The number of segments of the raw request path covered by the route, including any
+    // empty segments among them.
+    consumed: usize,
+    // Whether the route covers the whole of the request's path.
+    complete: bool,
+    // Whether the route was found by matching the path folded, for matches mounted with
+    // `mount_ci`, or literally, for the others. `None` when the path was matched once for all.
+    fold_case: Option<bool>,
+    matches: &'a [Match],
+    params: Vec<(String, String)>
+}
+
+// The outcome of searching for the match for a request.
+enum Lookup<'a> {
+    // The match, the number of request path segments its route covers, and any params.
+    Matched(&'a Match, usize, Vec<(String, String)>),
+    // No match accepted the request.
+    Unmatched(Rejection)
+}
+
+// Why no match accepted a request.
+struct Rejection {
+    error: MountError,
+    // The methods of the matches rejected because of the request's method.
+    allowed: Vec<Method>
+}
+
+/// The error returned by `Mount` when a request isn't passed to any mounted handler.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MountError {
+    /// No handler is mounted on the request's path, which is given with a leading slash,
+    /// such as `/foo/bar`. For a nested `Mount`, the path is that of the `OriginalUrl`, so it
+    /// is the path as it was requested, before enclosing mounts stripped or rewrote it.
+    /// This results in a `404 Not Found`, or the response set with
+    /// `Mount::no_match_response`.
+    NoRoute {
+        /// The path which didn't match any route.
+        path: String
+    },
+    /// Handlers are mounted on the request's path, but only for other methods. This results
+    /// in a `405 Method Not Allowed`.
+    MethodNotAllowed,
+    /// Handlers are mounted on the request's path with `Mount::mount_accept`, but none for a
+    /// media type the request's `Accept` header allows, and no other handler matched. This
+    /// results in a `406 Not Acceptable`.
+    NotAcceptable,
+    /// Returned by a fallback handler to decline a request, so that the next fallback set
+    /// with `Mount::fallback` is tried. Once every fallback has declined, the request results
+    /// in the error it would have without any fallbacks. It should not be returned by other
+    /// handlers, for which it has no special meaning.
+    NotHandled,
+    /// Handlers are mounted on the request's path, but every one which could have served it
+    /// was mounted with `Mount::mount_if` and its predicate declined the request. This
+    /// results in the same response as `NoRoute`.
+    Declined,
+    /// The request matched a handler which has been disabled with `Mount::set_enabled`.
+    /// This results in a `503 Service Unavailable`, or the status set with
+    /// `Mount::disabled_response`.
+    Disabled,
+    /// The request's path exceeds a limit set with `Mount::max_depth`. This results in a
+    /// `414 URI Too Long`, or the status set with `Mount::limit_response`.
+    PathTooLong,
+    /// A segment of the request's path exceeds a limit set with `Mount::max_segment_len`.
+    /// This results in a `414 URI Too Long`, or the status set with `Mount::limit_response`.
+    SegmentTooLong
 }
 
 /// The error returned by `Mount` when a request doesn't match any mounted handlers.
+///
+/// This is an alias of `MountError`, kept for compatibility.
+pub type NoMatch = MountError;
+
+impl Error for MountError {
+    fn description(&self) -> &'static str {
+        match *self {
+            MountError::NoRoute { .. } => "No Match",
+            MountError::MethodNotAllowed => "Method Not Allowed",
+            MountError::NotAcceptable => "Not Acceptable",
+            MountError::NotHandled => "Not Handled",
+            MountError::Declined => "Declined",
+            MountError::Disabled => "Disabled",
+            MountError::PathTooLong => "Path Too Long",
+            MountError::SegmentTooLong => "Segment Too Long"
+        }
+    }
+}
+
+impl fmt::Display for MountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MountError::NoRoute { ref path } => write!(f, "No match for {}", path),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+/// How `Mount::lookup` would match a request path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchInfo {
+    /// The segments of the matched route, such as `["users", ":id"]`.
+    pub route: Vec<String>,
+    /// The number of segments which would be stripped from the path.
+    pub length: usize,
+    /// The values captured by wildcard segments in the route, as found in `Params`.
+    pub params: HashMap<String, String>
+}
+
+/// The error returned by `Mount::try_mount` when a handler is already mounted on the route.
 #[derive(Debug)]
-pub struct NoMatch;
+pub struct AlreadyMounted {
+    /// The route which was already occupied.
+    pub route: String
+}
+
+impl Error for AlreadyMounted {
+    fn description(&self) -> &'static str { "Already Mounted" }
+}
+
+impl fmt::Display for AlreadyMounted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.description(), self.route)
+    }
+}
+
+/// The error returned by the `mount_checked!` macro when a route is malformed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidRoute {
+    /// The route which was rejected.
+    pub route: String,
+    /// What is wrong with the route, such as `contains an empty segment`.
+    pub problem: String
+}
 
-impl Error for NoMatch {
-    fn description(&self) -> &'static str { "No Match" }
+impl Error for InvalidRoute {
+    fn description(&self) -> &'static str { "Invalid Route" }
 }
 
-impl fmt::Display for NoMatch {
+impl fmt::Display for InvalidRoute {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(self.description())
+        write!(f, "{}: {:?} {}", self.description(), self.route, self.problem)
     }
 }
 
@@ -49,127 +519,4298 @@ impl Mount {
     /// Creates a new instance of `Mount`.
     pub fn new() -> Mount {
         Mount {
-            inner: SequenceTrie::new()
+            inner: SequenceTrie::new(),
+            fallbacks: Vec::new(),
+            defaults: SequenceTrie::new(),
+            no_match: None,
+            disabled_status: status::ServiceUnavailable,
+            on_overwrite: None,
+            on_dispatch: None,
+            case_insensitive: false,
+            folded_routes: false,
+            redirect_trailing_slash: false,
+            count_requests: false,
+            forward_prefix: false,
+            strip_forwarded_prefix: false,
+            auto_options: false,
+            strict: false,
+            root_empty_path: false,
+            reset_original_url: false,
+            exact_paths: false,
+            global_prefix: Vec::new(),
+            max_depth: None,
+            max_segment_len: None,
+            limit_status: status::UriTooLong
         }
     }
 
+    /// Creates a `Mount` with each handler mounted on its route.
+    ///
+    /// The pairs are mounted in order, so where a route appears more than once the last
+    /// handler given for it wins.
+    pub fn from_routes<I>(routes: I) -> Mount
+    where I: IntoIterator<Item=(String, Box<Handler>)> {
+        let mut mount = Mount::new();
+        Extend::extend(&mut mount, routes);
+        mount
+    }
+
+    /// Creates a `Mount` with a single handler mounted on a route.
+    ///
+    /// Further handlers can be added with `route`, so that a `Mount` can be built in one
+    /// expression, such as `Mount::with("/api", api).route("/", files)`, and passed straight
+    /// to `Iron::new`.
+    pub fn with<R: AsRef<str>, H: Handler>(route: R, handler: H) -> Mount {
+        Mount::new().route(route, handler)
+    }
+
+    /// Mounts a given `Handler` onto a route, taking and returning the `Mount` by value.
+    ///
+    /// This behaves like `mount`, but suits building an owned `Mount` by chaining calls.
+    pub fn route<R: AsRef<str>, H: Handler>(mut self, route: R, handler: H) -> Mount {
+        self.mount(route, handler);
+        self
+    }
+
     /// Mounts a given `Handler` onto a route.
     ///
-    /// This method may be called multiple times with different routes.
+    /// This method may be called multiple times with different routes. The route may be given
+    /// as any string type, such as a `String` built at runtime.
     /// For a given request, the *most specific* handler will be selected.
     ///
-    /// Existing handlers on the same route will be overwritten.
-    pub fn mount<H: Handler>(&mut self, route: &str, handler: H) -> &mut Mount {
-        // Parse the route into a list of strings. The unwrap is safe because strs are UTF-8.
-        let key: Vec<String> = Path::new(route).components().flat_map(|c|
-            match c {
-                Component::RootDir => None,
-                c => Some(c.as_os_str().to_str().unwrap().to_string())
-            }.into_iter()
-        ).collect();
+    /// Existing handlers on the same route will be overwritten. A function to be notified of
+    /// this can be set with `Mount::on_overwrite`.
+    ///
+    /// A handler mounted on `/` is a catch-all: it receives any request not matched by a more
+    /// specific route, with the path left untouched.
+    ///
+    /// Any `.` and `..` segments in the route are resolved, so `/a/../b` is mounted as `/b`.
+    /// A trailing slash is ignored, so `/docs/` is the same route as `/docs` and receives
+    /// requests below it too; use `mount_exact` for a handler on the directory only.
+    ///
+    /// A segment of the form `:name` is a wildcard which matches any single, non-empty segment
+    /// of the request path, such as `/users/:id/profile` matching `/users/42/profile`. The
+    /// captured values are available to the handler through the `Params` key in
+    /// `Request::extensions`. Wildcards follow the usual longest-prefix rule: the route
+    /// matching the most segments of the path is selected, and params are only captured from
+    /// that route. Where several routes match the same number of segments, static segments
+    /// are preferred to wildcards, comparing from the start of the route.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the route is empty or only whitespace, which is usually a configuration
+    /// value that was never filled in, rather than silently mounting a catch-all. Use `/` to
+    /// mount on the root. The same applies to every method which mounts a handler on a route,
+    /// while methods which look a route up, such as `handler_at` or `unmount`, treat an empty
+    /// route as one which nothing is mounted on.
+    pub fn mount<R: AsRef<str>, H: Handler>(&mut self, route: R, handler: H) -> &mut Mount {
+        self.mount_arc(route, Arc::new(handler))
+    }
+
+    /// Mounts a shared `Handler` onto a route.
+    ///
+    /// This behaves like `mount`, but allows a single handler instance to be mounted on
+    /// several routes, or kept by the caller, without being copied. This is useful for
+    /// handlers which own expensive state.
+    pub fn mount_arc<R: AsRef<str>>(&mut self, route: R, handler: Arc<Handler>) -> &mut Mount {
+        let key = self.mount_key(route.as_ref());
 
         // Insert a match struct into the trie.
-        self.inner.insert(key.as_ref(), Match {
-            handler: Box::new(handler) as Box<Handler>,
-            length: key.len()
+        self.insert(&key, Match::new(handler, key.len()));
+        self
+    }
+
+    /// Mounts a `MountedHandler` onto a route, calling its `on_mount` once it is mounted.
+    ///
+    /// This behaves like `mount`, except that the handler's `on_unmount` is called when it
+    /// is later removed from this `Mount`. Handlers mounted with `mount` are never told of
+    /// either, even if they implement `MountedHandler`.
+    pub fn mount_lifecycle<H: MountedHandler>(&mut self, route: &str, handler: H)
+                                              -> &mut Mount {
+        let key = self.mount_key(route);
+        let handler = Arc::new(handler);
+        let hook = handler.clone();
+
+        self.insert(&key, Match {
+            on_unmount: Some(Arc::new(move || hook.on_unmount())),
+            ..Match::new(handler.clone(), key.len())
         });
+        handler.on_mount();
         self
     }
-}
 
-impl Handler for Mount {
-    fn handle(&self, req: &mut Request) -> IronResult<Response> {
-        // Find the matching handler.
-        let matched = {
-            // Extract the request path.
-            let path = &*req.url.path;
-
-            // If present, remove the trailing empty string (which represents a trailing slash).
-            // If it isn't removed the path will never match anything, because
-            // Path::str_components ignores trailing slashes and will never create routes
-            // ending in "".
-            let key = match path.last() {
-                Some(s) if s.is_empty() => &path[..path.len() - 1],
-                _ => path
-            };
+    /// Mounts a given `Handler` onto a route, returning the number of segments in the route.
+    ///
+    /// This behaves like `mount`, but returns the route's depth once parsed, which is the
+    /// number of segments stripped from requests passed to the handler, so that callers can
+    /// use it, for example to label metrics, without parsing the route themselves. A handler
+    /// mounted on `/` has a depth of `0`.
+    pub fn mount_with_depth<H: Handler>(&mut self, route: &str, handler: H) -> usize {
+        let key = self.mount_key(route);
+        let length = key.len();
+        self.insert(&key, Match::new(Arc::new(handler), length));
+        length
+    }
 
-            // Search the Trie for the nearest most specific match.
-            match self.inner.get_ancestor(key) {
-                Some(matched) => matched,
-                None => return Err(IronError::new(NoMatch, status::NotFound))
-            }
-        };
+    /// Mounts a given `Handler` onto a route given as its segments.
+    ///
+    /// This behaves like `mount`, except that the segments are used exactly as given rather
+    /// than parsed from a route string, so `.`, `..` and empty segments are not resolved
+    /// or removed. Segments are still folded for matching as usual, for example while
+    /// matching case-insensitively, and a segment of the form `:name` is still a wildcard.
+    /// Since request paths never contain empty, `.` or `..` segments, a route containing one
+    /// can't match anything; `validate` reports such routes.
+    pub fn mount_segments<H: Handler>(&mut self, segments: &[&str], handler: H) -> &mut Mount {
+        let key: Vec<String> = segments.iter()
+            .map(|segment| self.fold_segment(segment.to_string()))
+            .collect();
 
-        // We have a match, so fire off the child.
-        // If another mount middleware hasn't already, insert the unmodified url
-        // into the extensions as the "original url".
-        let is_outer_mount = !req.extensions.contains::<OriginalUrl>();
-        if is_outer_mount {
-            req.extensions.insert::<OriginalUrl>(req.url.clone());
-        }
+        self.insert(&key, Match::new(Arc::new(handler), segments.len()));
+        self
+    }
 
-        // Remove the prefix from the request's path before passing it to the mounted handler.
-        // If the prefix is entirely removed and no trailing slash was present, the new path
-        // will be the empty list. For the purposes of redirection, conveying that the path
-        // did not include a trailing slash is more important than providing a non-empty list.
-        req.url.path = req.url.path[matched.length..].to_vec();
+    /// Mounts a given `Handler` onto a route which is matched case-insensitively, whether or
+    /// not `case_insensitive` is enabled.
+    ///
+    /// Other routes are still matched exactly, so case-sensitive routes, such as those
+    /// containing tokens, can be mounted alongside case-insensitive ones. Where a route
+    /// mounted with `mount` and one mounted with `mount_ci` would match a request and are of
+    /// the same length, the one mounted with `mount` is preferred, as the request matches it
+    /// literally. Longer routes are still preferred over shorter ones, whichever way they
+    /// were mounted.
+    ///
+    /// A handler mounted this way is not replaced by `mount`, nor removed by `unmount`.
+    /// Existing handlers mounted with `mount_ci` on the same route will be overwritten.
+    pub fn mount_ci<H: Handler>(&mut self, route: &str, handler: H) -> &mut Mount {
+        let key: Vec<String> = self.mount_key(route).into_iter()
+            .map(fold_case)
+            .collect();
 
-        let res = matched.handler.handle(req);
+        self.insert(&key, Match {
+            fold_case: true,
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
 
-        // Reverse the URL munging, for future middleware.
-        req.url = match req.extensions.get::<OriginalUrl>() {
-            Some(original) => original.clone(),
-            None => panic!("OriginalUrl unexpectedly removed from req.extensions.")
-        };
+    /// Mounts a given `Handler` onto a route with a priority.
+    ///
+    /// This behaves like `mount`, except for how the handler is ordered against others. The
+    /// handler for a request is chosen from those whose routes match the request's path by
+    /// comparing, in order:
+    ///
+    /// 1. The number of segments in the route, preferring longer routes.
+    /// 2. The priority, preferring higher priorities. Handlers mounted in other ways have a
+    ///    priority of `0`.
+    /// 3. Each segment of the route from the start, preferring static segments to wildcards.
+    /// 4. Handlers mounted with more restrictions, such as by host or method, first.
+    /// 5. The order the handlers were mounted in, preferring earlier handlers.
+    ///
+    /// For example, a handler mounted on `/users/:id` with priority `1` takes precedence over
+    /// one mounted on `/users/me` with `mount`.
+    pub fn mount_priority<H: Handler>(&mut self, route: &str, priority: i32, handler: H)
+                                      -> &mut Mount {
+        let key = self.mount_key(route);
 
-        // If this mount middleware is the outermost mount middleware,
-        // remove the original url from the extensions map to prevent leakage.
-        if is_outer_mount {
-            req.extensions.remove::<OriginalUrl>();
+        self.insert(&key, Match {
+            priority: priority,
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
+
+    /// Mounts a single `Handler` onto several routes.
+    ///
+    /// The handler is shared between the routes as with `mount_arc`, so it needn't be
+    /// `Clone` and only one instance exists. Each route strips its own prefix, so a handler
+    /// on `/images` and `/img` sees `/img/logo.png` as `/logo.png`.
+    pub fn mount_all<H: Handler>(&mut self, routes: &[&str], handler: H) -> &mut Mount {
+        self.mount_shared(routes, Arc::new(handler))
+    }
+
+    /// Mounts a shared `Handler` onto several routes.
+    ///
+    /// This behaves like `mount_all`, but takes a handler the caller may also keep a
+    /// reference to, such as one also mounted on another `Mount`. No matter how many routes
+    /// it is mounted on, only the one instance exists.
+    pub fn mount_shared(&mut self, routes: &[&str], handler: Arc<Handler>) -> &mut Mount {
+        for route in routes {
+            self.mount_arc(route, handler.clone());
         }
+        self
+    }
 
-        res
+    /// Mounts a closure onto a route.
+    ///
+    /// This behaves like `mount`. Closures already implement `Handler`, but naming the closure
+    /// type here lets its argument and return types be inferred, so inline handlers can be
+    /// written as `mount.mount_fn("/ping", |_| Ok(Response::with((status::Ok, "pong"))))`.
+    pub fn mount_fn<F>(&mut self, route: &str, f: F) -> &mut Mount
+    where F: Send + Sync + 'static + Fn(&mut Request) -> IronResult<Response> {
+        self.mount(route, f)
     }
-}
 
-#[cfg(test)]
-mod tests {    
-    use super::Mount;
-    use iron::{Request, Response, IronResult, Url};
-    use hyper::method::Method;
-    use hyper::buffer::BufReader;
-    use hyper::net::NetworkStream;
-    use std::io::Cursor;
-    use iron::middleware::Handler;
+    /// Mounts a handler which responds to every request with a fixed status and body, such
+    /// as for health checks and stub endpoints.
+    ///
+    /// The body is copied, so it needn't outlive the `Mount`.
+    pub fn mount_static(&mut self, route: &str, status: status::Status, body: &str)
+                        -> &mut Mount {
+        let body = body.to_owned();
+        self.mount_fn(route, move |_| Ok(Response::with((status, body.clone()))))
+    }
 
-    fn send_hello(_: &mut Request) -> IronResult<Response> {
-        Ok(Response::new())
+    /// Mounts a given `Handler` onto a route without stripping the route from the path.
+    ///
+    /// The route is used for matching in the same way as for `mount`, but the handler
+    /// receives the request's path in full. This suits handlers such as reverse proxies which
+    /// need the whole original path.
+    pub fn mount_passthrough<H: Handler>(&mut self, route: &str, handler: H) -> &mut Mount {
+        let key = self.mount_key(route);
+
+        self.insert(&key, Match {
+            strip: false,
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
     }
 
-    #[test]
-    fn it_mounts() {
-        let mut mount = Mount::new();
-        mount.mount("/testing", send_hello);
-        mount.mount("/another/file.png", send_hello);
-        assert!(test_url_matches(&mount, "http://localhost/testing"));
-        assert!(test_url_matches(&mount, "http://localhost/testing/"));
-        assert!(test_url_matches(&mount, "http://localhost/testing/something"));
-        assert!(! test_url_matches(&mount, "http://localhost/test"));
-        assert!(! test_url_matches(&mount, "http://localhost/test/testing"));
-        assert!(test_url_matches(&mount, "http://localhost/another/file.png"));
-        assert!(! test_url_matches(&mount, "http://localhost/another/file.jpg"));
+    /// Mounts a given `Handler` onto a route, replacing the route with another prefix in the
+    /// path the handler receives.
+    ///
+    /// This behaves like `mount`, except that once the route is stripped from the path,
+    /// the segments of `rewrite_to` are put in its place. For example, a handler mounted on
+    /// `/legacy` with a `rewrite_to` of `/v2` sees `/legacy/users` as `/v2/users`. The
+    /// request's path is restored as usual afterwards. `MountedPath` is still the prefix
+    /// actually stripped from the request, `/legacy`.
+    pub fn mount_rewrite<H: Handler>(&mut self, route: &str, rewrite_to: &str, handler: H)
+                                     -> &mut Mount {
+        let key = self.mount_key(route);
+        let rewrite = parse_route(rewrite_to).iter()
+            .map(|segment| utf8_percent_encode(segment, DEFAULT_ENCODE_SET))
+            .collect();
+
+        self.insert(&key, Match {
+            rewrite: rewrite,
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
     }
 
-    fn test_url_matches(mount: &Mount, url: &str) -> bool {
-        let data = Cursor::new("".to_string().into_bytes());
-        let mut stream = mock::MockStream::new(data);
-        let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
-        let mut req = mock::request::new(Method::Get, Url::parse(url).unwrap(),
-            &mut reader);
-        let res = mount.handle(& mut req);
-        res.is_ok()
+    /// Mounts a given `Handler` onto a route, for requests to exactly that route only.
+    ///
+    /// Unlike `mount`, the handler doesn't receive requests for paths below the route. For
+    /// those, the search continues with shorter routes as though the handler wasn't there. A
+    /// trailing slash on the request's path is ignored.
+    ///
+    /// A handler mounted with `mount_exact` is preferred over one mounted on the same route
+    /// with `mount`, so the two can be combined to handle a route differently from the paths
+    /// below it.
+    pub fn mount_exact<H: Handler>(&mut self, route: &str, handler: H) -> &mut Mount {
+        let key = self.mount_key(route);
+
+        self.insert(&key, Match {
+            exact: true,
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
+
+    /// Mounts a given `Handler` onto a route, for requests to a specific host only.
+    ///
+    /// Requests are first matched against the handlers mounted for the host named by their
+    /// `Host` header, ignoring any port, and then against the handlers mounted with `mount`.
+    /// So a request which matches a host-scoped handler will be passed to it even if a more
+    /// specific host-agnostic handler exists.
+    ///
+    /// Host names are matched case-insensitively. A port in `host` is ignored. The host may
+    /// start with a `*` wildcard label, as in `*.example.com`, to match requests for any
+    /// single subdomain such as `api.example.com`, but not `example.com` itself. Handlers
+    /// mounted for a wildcard are only tried once no handler mounted for the exact host
+    /// matches the request.
+    ///
+    /// Existing handlers on the same host and route will be overwritten.
+    pub fn mount_host<H: Handler>(&mut self, host: &str, route: &str, handler: H)
+                                  -> &mut Mount {
+        let key = self.mount_key(route);
+
+        self.insert(&key, Match {
+            host: Some(normalize_host(host)),
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
+
+    /// Mounts a given `Handler` onto a route, for requests with a specific method only.
+    ///
+    /// Handlers mounted with `mount` continue to match requests with any method, but a
+    /// handler mounted for the request's method is preferred over one on the same route
+    /// mounted for any method.
+    ///
+    /// If handlers are mounted on the request's path for other methods only, and no handler
+    /// mounted for any method matches, the request results in a `405 Method Not Allowed`
+    /// error without consulting the fallback. Its response has an `Allow` header listing the
+    /// methods which handlers were mounted for.
+    ///
+    /// Existing handlers on the same route and method will be overwritten.
+    pub fn mount_method<H: Handler>(&mut self, method: Method, route: &str, handler: H)
+                                    -> &mut Mount {
+        let key = self.mount_key(route);
+
+        self.insert(&key, Match {
+            method: Some(method),
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
+
+    /// Mounts a given `Handler` onto a route, for requests satisfying a predicate only.
+    ///
+    /// The handler is only considered for requests for which `predicate` returns `true`. When
+    /// it returns `false`, the search continues as though the handler wasn't mounted: first
+    /// with any other handlers on the same route, then with shorter routes, and finally with
+    /// the fallback. A guarded handler is preferred over one mounted on the same route with
+    /// `mount`.
+    ///
+    /// Since predicates can't be compared, this never overwrites an existing handler. Guarded
+    /// handlers on the same route are tried in the order they were mounted.
+    pub fn mount_if<H, P>(&mut self, route: &str, predicate: P, handler: H) -> &mut Mount
+    where H: Handler, P: Fn(&Request) -> bool + Send + Sync + 'static {
+        let key = self.mount_key(route);
+
+        self.insert(&key, Match {
+            predicate: Some(Arc::new(predicate)),
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
+
+    /// Mounts a given `Handler` onto a route, for requests whose query string contains a
+    /// specific key and value only.
+    ///
+    /// For example, `mount_query("/handler", "mode", "legacy", h)` matches requests for
+    /// `/handler?mode=legacy&page=2`. Keys and values are compared after decoding. When the
+    /// pair is absent, including when the request has no query string at all, the search
+    /// continues with any other handlers on the route, such as one mounted with `mount`,
+    /// then with shorter routes.
+    ///
+    /// A query-scoped handler is preferred over one mounted on the same route with `mount`.
+    /// Where several query-scoped handlers on a route match, the one mounted first is used.
+    /// Existing handlers on the same route, key and value will be overwritten.
+    pub fn mount_query<H: Handler>(&mut self, route: &str, key: &str, value: &str, handler: H)
+                                   -> &mut Mount {
+        let route_key = self.mount_key(route);
+
+        self.insert(&route_key, Match {
+            query: Some((key.to_string(), value.to_string())),
+            ..Match::new(Arc::new(handler), route_key.len())
+        });
+        self
+    }
+
+    /// Mounts a given `Handler` onto a route, for requests which accept a specific media type
+    /// only, such as `text/html`.
+    ///
+    /// Where several handlers on a route are mounted for media types the request's `Accept`
+    /// header allows, the one whose media type it gives the highest quality is used, and of
+    /// those the one mounted first. A media type's quality is that of the most specific range
+    /// matching it, so `text/html` is preferred over `text/*`, which is preferred over `*/*`.
+    /// A request without an `Accept` header accepts any media type.
+    ///
+    /// When no media type is acceptable, the search continues with any other handlers on the
+    /// route, such as one mounted with `mount`, then with shorter routes. If none matches,
+    /// the request results in a `406 Not Acceptable` error without consulting the fallback.
+    ///
+    /// Existing handlers on the same route and media type will be overwritten.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the media type isn't of the form `type/subtype`.
+    pub fn mount_accept<H: Handler>(&mut self, route: &str, media_type: &str, handler: H)
+                                    -> &mut Mount {
+        let key = self.mount_key(route);
+        let media_type = media_type.trim().to_lowercase();
+        let (top, sub) = match media_type.find('/') {
+            Some(i) if i > 0 && i + 1 < media_type.len() => {
+                (&media_type[..i], &media_type[i + 1..])
+            },
+            _ => panic!("Media type {:?} is not of the form type/subtype.", media_type)
+        };
+
+        self.insert(&key, Match {
+            media_type: Some((top.to_string(), sub.to_string())),
+            ..Match::new(Arc::new(handler), key.len())
+        });
+        self
+    }
+
+    /// Mounts a given `Handler` onto a route, unless a handler is already mounted there.
+    ///
+    /// This behaves like `mount`, except that instead of overwriting an existing handler
+    /// on exactly the same route it leaves the `Mount` unchanged and returns an error.
+    pub fn try_mount<H: Handler>(&mut self, route: &str, handler: H)
+                                 -> Result<&mut Mount, AlreadyMounted> {
+        let occupied = self.plain_match(&self.mount_key(route)).is_some();
+        if occupied {
+            return Err(AlreadyMounted { route: route.to_string() });
+        }
+        Ok(self.mount(route, handler))
     }
 
+    /// Mounts a given `Handler` onto a route if no handler is already mounted there, returning
+    /// whether it was mounted.
+    ///
+    /// Like `try_mount`, an existing handler on exactly the same route is left in place, so
+    /// the first handler mounted on a route wins, but the outcome is reported as a `bool`.
+    pub fn mount_or_else<H: Handler>(&mut self, route: &str, handler: H) -> bool {
+        let occupied = self.plain_match(&self.mount_key(route)).is_some();
+        if !occupied {
+            self.mount(route, handler);
+        }
+        !occupied
+    }
+
+    /// Removes the `Handler` mounted on a route, returning it if one existed.
+    ///
+    /// The route is parsed in the same way as for `mount`. Only the handler mounted on
+    /// exactly this route is removed; handlers mounted on more specific routes beneath it
+    /// are left in place.
+    ///
+    /// A handler mounted with `mount_arc` is returned boxed, and remains shared with any
+    /// other routes it is mounted on.
+    pub fn unmount(&mut self, route: &str) -> Option<Box<Handler>> {
+        let key = match self.existing_key(route) {
+            Some(key) => key,
+            None => return None
+        };
+        self.remove_plain(&key).map(|matched| {
+            matched.unmounted();
+            Box::new(Shared(matched.handler)) as Box<Handler>
+        })
+    }
+
+    /// Returns `true` if any handler is mounted on a route at or beneath a prefix.
+    ///
+    /// The prefix is parsed in the same way as a route for `mount`. Handlers mounted only for
+    /// a particular host or method count, but the fallback does not.
+    pub fn has_subtree(&self, prefix: &str) -> bool {
+        self.existing_key(prefix)
+            .and_then(|key| self.inner.get_node(&key))
+            .map_or(false, |node| node.values().next().is_some())
+    }
+
+    /// Returns the routes at or beneath a prefix which have handlers mounted on them, sorted,
+    /// or an empty list if there are none.
+    ///
+    /// The prefix is parsed in the same way as a route for `mount`, and the routes are given
+    /// in full, including the prefix, in the same form as by `routes`. As with `has_subtree`,
+    /// routes with handlers mounted only for a particular host or method are included.
+    pub fn routes_under(&self, prefix: &str) -> Vec<String> {
+        let key = match self.existing_key(prefix) {
+            Some(key) => key,
+            None => return Vec::new()
+        };
+        let mut routes: Vec<String> = match self.inner.get_node(&key) {
+            Some(node) => node.keys().map(|rest| {
+                let route: Vec<&String> = key.iter().chain(rest).collect();
+                format_route(&route)
+            }).collect(),
+            None => Vec::new()
+        };
+        routes.sort();
+        routes
+    }
+
+    /// Removes every handler mounted on a route at or beneath a prefix, returning how many
+    /// were removed.
+    ///
+    /// Unlike `unmount`, this removes handlers mounted for a particular host or method too,
+    /// so that all of the routes registered for a feature can be torn down together. Defaults
+    /// set with `mount_default` at or beneath the prefix are removed as well, but aren't
+    /// counted. The fallback is left in place.
+    pub fn unmount_subtree(&mut self, prefix: &str) -> usize {
+        let key = match self.existing_key(prefix) {
+            Some(key) => key,
+            None => return 0
+        };
+
+        let default_key: Vec<String> = key.iter().filter(|s| !s.is_empty()).cloned().collect();
+        if let Some(node) = self.defaults.get_mut_node(&default_key) {
+            node.value = None;
+            node.children.clear();
+        }
+        self.defaults.remove(&default_key);
+
+        let removed = match self.inner.get_mut_node(&key) {
+            Some(node) => {
+                let removed = node.values().fold(0, |count, matches| {
+                    for matched in matches { matched.unmounted(); }
+                    count + matches.len()
+                });
+                node.value = None;
+                node.children.clear();
+                removed
+            },
+            None => return 0
+        };
+
+        // Prune the nodes leading to the prefix which no longer lead to a handler.
+        self.inner.remove(&key);
+        removed
+    }
+
+    /// Mounts a `Handler` onto a route, returning the handler it replaced, if any.
+    ///
+    /// This behaves like `mount`, but gives the caller the previous handler on exactly this
+    /// route, as `unmount` would, so that it can be shut down cleanly when swapping routes
+    /// on a live `Mount`.
+    pub fn replace<H: Handler>(&mut self, route: &str, handler: H) -> Option<Box<Handler>> {
+        let key = self.mount_key(route);
+        self.insert(&key, Match::new(Arc::new(handler), key.len()))
+            .map(|matched| Box::new(Shared(matched.handler)) as Box<Handler>)
+    }
+
+    /// Enables or disables the handlers mounted on exactly a route, without unmounting them.
+    ///
+    /// Requests matching a disabled handler aren't passed to it, nor on to other handlers or
+    /// the fallback; they result in a `MountError::Disabled` error with a `503` status, which
+    /// can be changed with `Mount::disabled_response`. This applies to every handler on the
+    /// route, including those mounted for a particular host or method. Handlers are enabled
+    /// when mounted.
+    ///
+    /// Returns a `NoMatch` error if no handler is mounted on the route.
+    pub fn set_enabled(&mut self, route: &str, enabled: bool) -> Result<(), NoMatch> {
+        let key = match self.existing_key(route) {
+            Some(key) => key,
+            None => return Err(MountError::NoRoute { path: route.to_string() })
+        };
+        match self.inner.get_mut(&key) {
+            Some(matches) => {
+                for matched in matches.iter_mut() {
+                    matched.enabled = enabled;
+                }
+                Ok(())
+            },
+            None => Err(MountError::NoRoute { path: format_route(&key) })
+        }
+    }
+
+    /// Moves the `Handler` mounted on one route to another, keeping the same handler instance.
+    ///
+    /// Both routes are parsed in the same way as for `mount`, and only the handler mounted on
+    /// exactly `from` is moved, as with `unmount`. The handler then strips `to` from the
+    /// path instead of `from`. As with `mount`, it overwrites any handler already on `to`.
+    ///
+    /// Returns a `NoMatch` error, leaving the `Mount` unchanged, if no handler is mounted on
+    /// `from`.
+    pub fn remount(&mut self, from: &str, to: &str) -> Result<(), NoMatch> {
+        let from_key = match self.existing_key(from) {
+            Some(key) => key,
+            None => return Err(MountError::NoRoute { path: from.to_string() })
+        };
+        let to_key = self.mount_key(to);
+        match self.remove_plain(&from_key) {
+            Some(matched) => {
+                self.insert(&to_key, Match { length: to_key.len(), ..matched });
+                Ok(())
+            },
+            None => Err(MountError::NoRoute { path: format_route(&from_key) })
+        }
+    }
+
+    /// Returns the `Handler` mounted on exactly a route, if there is one.
+    ///
+    /// The route is parsed in the same way as for `mount`. Handlers mounted on ancestors of
+    /// the route, or only for a particular host or method, are not returned.
+    pub fn handler_at(&self, route: &str) -> Option<&Handler> {
+        self.existing_key(route)
+            .and_then(|key| self.plain_match(&key))
+            .map(|matched| &*matched.handler)
+    }
+
+    /// Returns the `Handler` a request would be dispatched to, without dispatching it.
+    ///
+    /// The request is matched exactly as by `handle`, including its host, method and any
+    /// other restrictions. If no mounted handler matches, the most specific default set with
+    /// `mount_default` which applies to the path is returned, or otherwise the fallback.
+    /// Nothing about the request is changed. Returns `None` if `handle` would refuse the
+    /// request, redirect it to add a trailing slash or answer it itself, as `auto_options`
+    /// does, instead.
+    pub fn peek_match(&self, req: &Request) -> Option<&Handler> {
+        if self.exceeded_limit(&req.url.path).is_some() {
+            return None;
+        }
+        let path = match self.prefix_length(req) {
+            Some(length) => &req.url.path[length..],
+            None => return None
+        };
+        if self.auto_options_response(req, path).is_some() {
+            return None;
+        }
+
+        match self.find_match(req, path) {
+            Lookup::Matched(matched, consumed, _) => {
+                let redirected = self.redirect_trailing_slash && !self.exact_paths &&
+                    consumed == path.len();
+                if matched.enabled && !redirected { Some(&*matched.handler) } else { None }
+            },
+            Lookup::Unmatched(Rejection { error: MountError::MethodNotAllowed, .. }) |
+            Lookup::Unmatched(Rejection { error: MountError::NotAcceptable, .. }) => None,
+            Lookup::Unmatched(_) => self.fallbacks_for(path).into_iter().next()
+        }
+    }
+
+    /// Determines which route a request path would be matched to, without dispatching it.
+    ///
+    /// `path` is a request path as found in `Request::url`. The path is matched in the same
+    /// way as by `handle`, except that restrictions which depend on the rest of the request,
+    /// such as host, method, query or predicate, are ignored. Any `global_prefix` is
+    /// stripped first, and a path which doesn't start with it matches nothing. Returns the
+    /// segments of the matched route and the number of segments which would be stripped
+    /// from the path, including those of the global prefix.
+    pub fn resolve(&self, path: &[String]) -> Option<(Vec<String>, usize)> {
+        self.lookup(path).map(|info| (info.route, info.length))
+    }
+
+    /// Determines how a request path would be matched, without dispatching it.
+    ///
+    /// This matches the path in the same way as `resolve`, but also returns the params that
+    /// would be captured by wildcard segments, so that routing decisions can be checked in
+    /// full without building a `Request`.
+    pub fn lookup(&self, path: &[String]) -> Option<MatchInfo> {
+        let prefix = match self.global_prefix_length(path) {
+            Some(prefix) => prefix,
+            None => return None
+        };
+        let candidates = self.candidates(&path[prefix..]);
+        let found = rank(&candidates).into_iter()
+            .find(|&(candidate, matched)| matched.accepts_path(candidate));
+        found.map(|(candidate, matched)| MatchInfo {
+            route: candidate.route.iter().map(|&s| s.clone()).collect(),
+            length: prefix + if matched.strip { candidate.consumed } else { 0 },
+            params: candidate.params.iter().cloned().collect()
+        })
+    }
+
+    /// Returns the methods a request path is served for, for building an `Allow` header.
+    ///
+    /// `path` is matched in the same way as by `resolve`, ignoring restrictions other than
+    /// method. If any handler which matches the path was mounted for any method, such as with
+    /// `mount`, every standard method is returned: `GET`, `HEAD`, `POST`, `PUT`, `DELETE`,
+    /// `CONNECT`, `OPTIONS`, `TRACE` and `PATCH`. Otherwise the methods of the handlers
+    /// mounted with `mount_method` which match it are returned, most specific route first,
+    /// which is empty if no handler matches the path at all.
+    pub fn allowed_methods(&self, path: &[String]) -> Vec<Method> {
+        match self.global_prefix_length(path) {
+            Some(prefix) => self.methods_for(&path[prefix..], |_| true),
+            None => Vec::new()
+        }
+    }
+
+    // The methods `allowed_methods` returns for a path below any global prefix, considering
+    // only the matches which `include` accepts.
+    fn methods_for<F>(&self, path: &[String], include: F) -> Vec<Method>
+    where F: Fn(&Match) -> bool {
+        let candidates = self.candidates(path);
+        let mut methods = Vec::new();
+        for (candidate, matched) in rank(&candidates) {
+            if !matched.accepts_path(candidate) || !include(matched) { continue; }
+            match matched.method {
+                Some(ref method) => {
+                    if !methods.contains(method) { methods.push(method.clone()); }
+                },
+                None => return vec![Method::Get, Method::Head, Method::Post, Method::Put,
+                                    Method::Delete, Method::Connect, Method::Options,
+                                    Method::Trace, Method::Patch]
+            }
+        }
+        methods
+    }
+
+    /// Returns the route of every mounted handler whose route is a prefix of a request path,
+    /// from the least specific to the most specific.
+    ///
+    /// `path` is matched in the same way as by `resolve`, except that every matching route
+    /// is returned rather than only the one a request would be dispatched to, so that
+    /// behaviour registered at each level of a path, such as authorization checks, can be
+    /// applied in turn. Nothing is dispatched.
+    pub fn matching_ancestors(&self, path: &[String]) -> Vec<Vec<String>> {
+        let prefix = match self.global_prefix_length(path) {
+            Some(prefix) => prefix,
+            None => return Vec::new()
+        };
+        self.candidates(&path[prefix..]).iter().rev()
+            .filter(|candidate| candidate.matches.iter().any(|m| m.accepts_path(candidate)))
+            .map(|candidate| candidate.route.iter().map(|&s| s.clone()).collect())
+            .collect()
+    }
+
+    /// Returns the part of a request path left once a matched route has been stripped from
+    /// it, exactly as `handle` passes it to the route's handler.
+    ///
+    /// `matched_route` is the route `full` was matched to, such as the route returned by
+    /// `lookup`; only its number of segments is used. As when matching, empty segments of
+    /// `full` within the route are stripped along with it, so `["api", "", "users"]` with
+    /// the route `["api"]` leaves `["users"]` rather than `["", "users"]`. Any `global_prefix`
+    /// is stripped too, while a path which doesn't start with it is returned unchanged.
+    pub fn strip_prefix<'a>(&self, full: &'a [String], matched_route: &[String]) -> &'a [String] {
+        match self.global_prefix_length(full) {
+            Some(prefix) => {
+                let below = &full[prefix..];
+                &below[raw_length(below, matched_route.len())..]
+            },
+            None => full
+        }
+    }
+
+    /// Builds the external URL of a mounted route.
+    ///
+    /// The route is appended to the path of `base`, which gives the scheme and host and the
+    /// prefix this `Mount` is itself mounted under, if any, such as the `OriginalUrl` with
+    /// its path replaced by the `MountedPath`. Any `global_prefix` is put between the two.
+    /// The query and fragment of `base` are dropped. Wildcard segments are left as they are.
+    ///
+    /// Returns `None` if no handler is mounted on exactly this route.
+    pub fn url_for(&self, route: &str, base: &Url) -> Option<Url> {
+        let key = match self.existing_key(route) {
+            Some(key) => key,
+            None => return None
+        };
+        if self.inner.get(&key).is_none() {
+            return None;
+        }
+
+        let mut url = base.clone();
+        if url.path.last().map_or(false, |s| s.is_empty()) {
+            url.path.pop();
+        }
+        url.path.extend(self.global_prefix.iter().chain(&key)
+                        .map(|s| utf8_percent_encode(s, DEFAULT_ENCODE_SET)));
+        if url.path.is_empty() {
+            url.path.push(String::new());
+        }
+        url.query = None;
+        url.fragment = None;
+        Some(url)
+    }
+
+    /// Returns whether a request for a path would be served, either by a mounted handler, by
+    /// a default set with `mount_default` or by the fallback.
+    ///
+    /// `path` is given as it appears in a URL, such as `/api/v1/`, and is matched as by
+    /// `resolve`, so a path outside any `global_prefix` is never served. Nothing is
+    /// dispatched, so this is safe to call from tests and admin handlers.
+    pub fn would_match(&self, path: &str) -> bool {
+        let path = if path.starts_with('/') { &path[1..] } else { path };
+        let segments: Vec<String> = path.split('/').map(|s| s.to_string()).collect();
+        match self.global_prefix_length(&segments) {
+            Some(prefix) => {
+                self.resolve(&segments).is_some() ||
+                    !self.fallbacks_for(&segments[prefix..]).is_empty()
+            },
+            None => false
+        }
+    }
+
+    /// Moves all of the handlers mounted on another `Mount` into this one, on the same routes.
+    ///
+    /// As with `mount`, a handler from `other` overwrites any handler already mounted here
+    /// on the same route (and host or method, if scoped). Only mounted handlers are moved;
+    /// `other`'s fallback and options are discarded.
+    ///
+    /// `Mount` also implements `Extend` for `(route, handler)` pairs, which mounts each pair
+    /// in turn; as this method shadows it, call it as `Extend::extend(&mut mount, routes)`.
+    pub fn extend(&mut self, mut other: Mount) -> &mut Mount {
+        for (key, matches) in other.take_routes() {
+            for matched in matches {
+                self.insert(&key, matched);
+            }
+        }
+        self
+    }
+
+    /// Moves all of the handlers mounted on another `Mount` into this one, beneath a prefix.
+    ///
+    /// A handler on the route `/users` in `other` is mounted here on `prefix` followed by
+    /// `/users`, and strips that whole route from the path, as though it had been mounted
+    /// there directly. As with `extend`, every handler is moved, overwriting any already
+    /// mounted on the same route, and `other`'s fallback and options are discarded. Use
+    /// `mount_flatten` to keep the routing `other` would have if mounted on `prefix`.
+    pub fn merge_under(&mut self, prefix: &str, mut other: Mount) -> &mut Mount {
+        let prefix_key = self.mount_key(prefix);
+        for (key, matches) in other.take_routes() {
+            for matched in matches {
+                self.insert_under(&prefix_key, &key, matched);
+            }
+        }
+        self
+    }
+
+    /// Mounts the handlers of another `Mount` under a prefix, as if `other` were mounted on
+    /// `prefix`, but without nesting one `Mount` inside another.
+    ///
+    /// Handlers are moved into this mount on `prefix` followed by their route, so a request
+    /// reaching them is matched with a single lookup and `OriginalUrl` is only cloned once.
+    /// They receive the same path, `MountedPath` and `Params` as they would through a nested
+    /// `Mount`. Handlers which can't be moved without changing how requests reach them,
+    /// such as those mounted with `mount_passthrough`, or only for a particular method or
+    /// host, are left in `other`, which is then mounted on `prefix` to serve them and its
+    /// fallback or `NoMatch` response as usual.
+    ///
+    /// Routing is unchanged as long as no other handlers are mounted under `prefix`. As with
+    /// `extend`, moved handlers overwrite any here on the same route, and they are matched
+    /// using this mount's options, such as `case_insensitive`, rather than `other`'s.
+    pub fn mount_flatten(&mut self, prefix: &str, mut other: Mount) -> &mut Mount {
+        let prefix_key = self.mount_key(prefix);
+
+        // A handler on the root of `other` receives every request under the prefix which no
+        // other handler does, so it can only be moved if nothing is left behind in `other`.
+        let root = other.remove_plain(&[]);
+
+        for (key, matches) in other.take_routes() {
+            let (moved, kept): (Vec<Match>, Vec<Match>) = matches.into_iter()
+                .partition(|m| m.strip && m.is_unconditional());
+
+            for matched in moved {
+                self.insert_under(&prefix_key, &key, matched);
+            }
+            if !kept.is_empty() {
+                other.inner.insert(&key, kept);
+            }
+        }
+
+        match root {
+            Some(root) if other.is_empty() => {
+                self.insert(&prefix_key, Match { length: prefix_key.len(), ..root });
+            },
+            root => {
+                if let Some(root) = root {
+                    other.insert(&[], root);
+                }
+                let length = prefix_key.len();
+                self.insert(&prefix_key, Match::new(Arc::new(other), length));
+            }
+        }
+        self
+    }
+
+    /// Adds a `Handler` to be used for requests which don't match any mounted handler.
+    ///
+    /// The fallback receives the request with its URL unmodified, as no prefix has been matched,
+    /// which suits handlers such as catch-all proxies that need the whole path. As with
+    /// mounted handlers, the `OriginalUrl` key is set, so a fallback of a nested `Mount` can
+    /// still see the path before any enclosing mount stripped its prefix. Without a fallback,
+    /// such requests result in a `NoMatch` error with a `404` status.
+    ///
+    /// Calling this method again adds another fallback after those already set, forming a
+    /// chain, such as a static file handler followed by a proxy and then an error page.
+    /// Fallbacks are tried in the order they were added, and one can decline a request by
+    /// returning an `IronError` whose error is `MountError::NotHandled`, passing it on to the
+    /// next. If every fallback declines, the request results in a `NoMatch` error as though
+    /// there were none.
+    pub fn fallback<H: Handler>(&mut self, handler: H) -> &mut Mount {
+        self.fallbacks.push(Arc::new(handler));
+        self
+    }
+
+    /// Sets a `Handler` to be used for requests beneath a prefix which don't match any
+    /// mounted handler, ahead of the fallbacks.
+    ///
+    /// For example, a default on `/api` can serve a JSON error for unmatched requests such as
+    /// `/api/missing`, while other unmatched requests are passed to an HTML fallback. Where
+    /// defaults are set on several prefixes of a request's path, the most specific is used,
+    /// and the fallbacks only when there is none or it declines the request by returning
+    /// `MountError::NotHandled`. Like a fallback, the default receives the request with its
+    /// URL unmodified. Requests refused as `405 Method Not Allowed` or
+    /// `406 Not Acceptable` are never passed to a default.
+    ///
+    /// The prefix is parsed in the same way as a route for `mount`, but its segments are
+    /// matched literally, so a segment of the form `:name` is not a wildcard. Setting a
+    /// default on the same prefix again replaces it.
+    pub fn mount_default<H: Handler>(&mut self, prefix: &str, handler: H) -> &mut Mount {
+        let mut key = self.mount_key(prefix);
+        key.retain(|segment| !segment.is_empty());
+        self.defaults.insert(&key, Arc::new(handler));
+        self
+    }
+
+    /// Sets the status of the `NoMatch` error returned for requests which don't match any
+    /// mounted handler, in place of `404 Not Found`.
+    ///
+    /// This only applies to requests which no fallback handler handles.
+    pub fn no_match_response(&mut self, status: status::Status) -> &mut Mount {
+        self.no_match_with(move || Response::with(status))
+    }
+
+    /// Sets the status of the error returned for requests matching a handler disabled with
+    /// `Mount::set_enabled`, in place of `503 Service Unavailable`.
+    pub fn disabled_response(&mut self, status: status::Status) -> &mut Mount {
+        self.disabled_status = status;
+        self
+    }
+
+    /// Sets a function to build the response of the `NoMatch` error returned for requests
+    /// which don't match any mounted handler, for example to give it a JSON body.
+    ///
+    /// This only applies to requests which no fallback handler handles.
+    pub fn no_match_with<F>(&mut self, f: F) -> &mut Mount
+    where F: Fn() -> Response + Send + Sync + 'static {
+        self.no_match = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets a function to be called with the route whenever mounting a handler replaces one
+    /// which was already mounted there, for example to log the collision.
+    ///
+    /// By default overwrites happen silently.
+    pub fn on_overwrite<F>(&mut self, f: F) -> &mut Mount
+    where F: Fn(&str) + Send + Sync + 'static {
+        self.on_overwrite = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets a function to be called with the route of the selected handler and the request
+    /// each time a request is dispatched to a mounted handler, for example to trace routing.
+    ///
+    /// The route is given as its segments, such as `["users", ":id"]`, and the request as the
+    /// handler will receive it, immediately before it is called. It isn't called for requests
+    /// passed to the fallback. By default nothing is called.
+    pub fn on_dispatch<F>(&mut self, f: F) -> &mut Mount
+    where F: Fn(&[String], &Request) + Send + Sync + 'static {
+        self.on_dispatch = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets whether routes are matched case-insensitively.
+    ///
+    /// When enabled, route segments are lowercased as they are mounted and request path
+    /// segments are lowercased before matching. Mounted handlers still see the request's
+    /// URL as it was sent, and `OriginalUrl` is unaffected.
+    ///
+    /// This only applies to routes mounted after it is enabled, so it should be set
+    /// before any handlers are mounted. It is disabled by default.
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Mount {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Sets whether requests for exactly a mounted route are redirected to add a trailing slash.
+    ///
+    /// When enabled, a request such as `/docs` which matches a handler mounted on `/docs`
+    /// exactly, without a trailing slash, receives a `301 Moved Permanently` redirect to
+    /// `/docs/` (keeping any query string) instead of being passed to the handler. This keeps
+    /// relative links in the handler's responses working. Requests which already have the
+    /// trailing slash, or which go deeper than the route, are unaffected.
+    ///
+    /// It is disabled by default, and has no effect while `exact_paths` is enabled.
+    pub fn redirect_trailing_slash(&mut self, yes: bool) -> &mut Mount {
+        self.redirect_trailing_slash = yes;
+        self
+    }
+
+    /// Sets whether a trailing slash distinguishes one route from another.
+    ///
+    /// By default a trailing slash is ignored, both in routes and in request paths, so `/foo`
+    /// and `/foo/` are the same route. When enabled, a route mounted with a trailing slash,
+    /// such as `/foo/`, only matches that path exactly, with the trailing slash, and its
+    /// handler receives an empty path; deeper paths such as `/foo/x` are matched to `/foo`,
+    /// or to another route, instead. A handler on `/foo` still receives `/foo/` as a path
+    /// below its route if no handler is mounted on `/foo/`, unless it was mounted with
+    /// `mount_exact`. Repeated slashes within the path are still
+    /// ignored. Since `/foo` is then a resource of its own, `redirect_trailing_slash` has no
+    /// effect while this is enabled.
+    ///
+    /// This only applies to routes mounted after it is enabled, so it should be set
+    /// before any handlers are mounted. It is disabled by default.
+    pub fn exact_paths(&mut self, yes: bool) -> &mut Mount {
+        self.exact_paths = yes;
+        self
+    }
+
+    /// Sets a prefix which is stripped from every request's path before it is matched, such
+    /// as a base path like `/myapp` under which a load balancer serves the application.
+    ///
+    /// Routes are then mounted without the prefix, and requests whose paths don't start
+    /// with it result in a `NoMatch` error, even if a fallback is set. The prefix is
+    /// included in `MountedPath`, and `OriginalUrl` is still the URL as it was received.
+    /// Setting it to `/` removes it. By default there is no global prefix.
+    pub fn global_prefix(&mut self, prefix: &str) -> &mut Mount {
+        self.global_prefix = self.mount_key(prefix);
+        self
+    }
+
+    /// Sets the greatest number of segments a request's path may have.
+    ///
+    /// Requests with longer paths are rejected with a `MountError::PathTooLong` error before
+    /// any routes are searched, which cheaply limits the work done for pathological requests.
+    /// Empty segments, from trailing or repeated slashes, aren't counted, as they are ignored
+    /// when matching. By default there is no limit.
+    pub fn max_depth(&mut self, n: usize) -> &mut Mount {
+        self.max_depth = Some(n);
+        self
+    }
+
+    /// Sets the greatest length, in bytes, of any one segment of a request's path.
+    ///
+    /// Requests with a longer segment are rejected with a `MountError::SegmentTooLong` error
+    /// before any routes are searched, so that very long segments from malicious clients
+    /// aren't hashed and compared while matching. The length of a segment is measured as it
+    /// appears in the URL, before percent-decoding. By default there is no limit.
+    pub fn max_segment_len(&mut self, n: usize) -> &mut Mount {
+        self.max_segment_len = Some(n);
+        self
+    }
+
+    /// Sets the status of the error returned for requests rejected by `Mount::max_depth` or
+    /// `Mount::max_segment_len`, in place of `414 URI Too Long`.
+    pub fn limit_response(&mut self, status: status::Status) -> &mut Mount {
+        self.limit_status = status;
+        self
+    }
+
+    /// Sets whether routes containing empty segments are rejected.
+    ///
+    /// Empty segments, as in `/api//v1`, are ignored when routes are parsed, so such a route
+    /// is mounted as `/api/v1`. In strict mode they are instead treated as a configuration
+    /// mistake. A single trailing slash is still allowed. It is disabled by default.
+    ///
+    /// # Panics
+    ///
+    /// In strict mode, methods taking a route panic if it contains an empty segment.
+    pub fn strict(&mut self, yes: bool) -> &mut Mount {
+        self.strict = yes;
+        self
+    }
+
+    /// Sets whether mounted handlers are told the prefix stripped from the path with an
+    /// `X-Forwarded-Prefix` header.
+    ///
+    /// When enabled, the header is set to the whole prefix stripped so far, including by
+    /// enclosing mounts, such as `/service-a`. This lets handlers such as reverse proxies
+    /// pass it on to build correct URLs. Handlers from whose path nothing was stripped, such
+    /// as those mounted on `/`, receive no header. The request's original header, if any, is
+    /// restored after the handler returns. It is disabled by default.
+    pub fn forward_prefix(&mut self, yes: bool) -> &mut Mount {
+        self.forward_prefix = yes;
+        self
+    }
+
+    /// Sets whether a prefix given by a request's `X-Forwarded-Prefix` header is stripped
+    /// from its path before it is matched.
+    ///
+    /// This is a per-request `global_prefix`, for running behind a proxy which serves the
+    /// application under a prefix such as `/myapp` and says so in the header. A request
+    /// without the header is matched as usual, while one with the header whose path doesn't
+    /// start with the prefix results in a `NoMatch` error. Any global prefix is expected to
+    /// follow the forwarded one. The prefix is included in `MountedPath`, and `OriginalUrl`
+    /// is still the URL as it was received. It is disabled by default.
+    pub fn strip_forwarded_prefix(&mut self, yes: bool) -> &mut Mount {
+        self.strip_forwarded_prefix = yes;
+        self
+    }
+
+    /// Sets whether `OPTIONS` requests are answered without dispatching them.
+    ///
+    /// When enabled, an `OPTIONS` request for a path which any handler matches receives an
+    /// empty `200 OK` response with an `Allow` header listing the methods given by
+    /// `allowed_methods`, such as for a CORS preflight request, and no handler is called.
+    /// Only handlers which are enabled and serve the request's host are taken into account,
+    /// and an `OPTIONS` request for a route whose handlers are all disabled is refused with
+    /// a `MountError::Disabled` error, as any other request for it would be. `OPTIONS`
+    /// requests for other paths are handled as usual. It is disabled by default, so
+    /// `OPTIONS` requests are dispatched like any other.
+    pub fn auto_options(&mut self, yes: bool) -> &mut Mount {
+        self.auto_options = yes;
+        self
+    }
+
+    /// Sets whether handlers are given the path `[""]` instead of an empty path.
+    ///
+    /// When a request's path is exactly a mounted route, as with `/blog` for a handler
+    /// mounted at `/blog`, stripping the route leaves the handler an empty path, while
+    /// `/blog/` leaves `[""]`. Keeping them distinct lets handlers redirect to add the
+    /// trailing slash; such redirects must be built from `OriginalUrl` or `MountedPath`,
+    /// since the stripped path no longer includes the mount prefix. Handlers which treat
+    /// both as their root can enable this to always see `[""]`, as a request for `/` does.
+    /// The request's path is restored as usual afterwards. It is disabled by default.
+    pub fn root_empty_path(&mut self, yes: bool) -> &mut Mount {
+        self.root_empty_path = yes;
+        self
+    }
+
+    /// Sets whether this mount records the URL it receives as the `OriginalUrl`, even when
+    /// mounted inside another `Mount`.
+    ///
+    /// By default only the outermost mount sets `OriginalUrl`, so every handler sees the URL
+    /// as the client sent it, and the outermost mount removes it again once its handler
+    /// returns. When enabled, this mount instead sets it to the URL as enclosing mounts have
+    /// rewritten it, letting its handlers resolve links relative to this mount's view; the
+    /// enclosing mount's value is restored after the handler returns. Trailing slash
+    /// redirects from mounts nested inside this one are then relative to it as well.
+    pub fn reset_original_url_per_level(&mut self, yes: bool) -> &mut Mount {
+        self.reset_original_url = yes;
+        self
+    }
+
+    /// Sets whether the requests dispatched to each mounted handler are counted.
+    ///
+    /// The counts are available from `Mount::stats`. Only requests actually passed to a
+    /// handler are counted, so for example requests declined by a `mount_if` predicate or
+    /// answered with a trailing slash redirect are not. It is disabled by default.
+    pub fn count_requests(&mut self, yes: bool) -> &mut Mount {
+        self.count_requests = yes;
+        self
+    }
+
+    /// Removes all mounted handlers, including defaults set with `mount_default`.
+    ///
+    /// The fallback handler and other settings are kept, so requests afterwards are passed to
+    /// the fallback if there is one and otherwise result in a `NoMatch` error.
+    pub fn clear(&mut self) {
+        for matched in self.inner.values().flat_map(|matches| matches) {
+            matched.unmounted();
+        }
+        self.inner = SequenceTrie::new();
+        self.defaults = SequenceTrie::new();
+    }
+
+    /// Returns the number of mounted handlers.
+    ///
+    /// The fallback handler, if any, is not counted.
+    pub fn len(&self) -> usize {
+        self.inner.values().fold(0, |count, matches| count + matches.len())
+    }
+
+    /// Returns `true` if no handlers are mounted.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the routes which currently have a handler mounted.
+    ///
+    /// Routes are yielded with a leading slash, e.g. `/api/v1`. They are not sorted, but
+    /// the order is stable for as long as the `Mount` is not modified.
+    pub fn routes<'a>(&'a self) -> Routes<'a> {
+        Routes {
+            inner: self.inner.keys()
+        }
+    }
+
+    /// Checks the mounted routes for configuration mistakes, returning a description of each
+    /// problem found.
+    ///
+    /// This reports handlers which can never receive a request, because a handler on the same
+    /// route with a higher priority accepts every request that would reach them, and routes
+    /// containing uppercase letters which can't match while routes are matched
+    /// case-insensitively. It also reports routes containing empty, `.` or `..` segments,
+    /// which can only be mounted with `mount_segments` and never match a request, since such
+    /// segments are removed from request paths. A trailing empty segment is allowed while
+    /// `exact_paths` is enabled, as that is how a route with a trailing slash is stored.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        for (key, matches) in self.inner.iter() {
+            let route = format_route(&key);
+
+            if self.case_insensitive &&
+               key.iter().any(|s| !is_wildcard(s) && s.chars().any(|c| c.is_uppercase())) {
+                problems.push(format!("{}: route contains uppercase letters, but routes are \
+                                       matched case-insensitively", route));
+            }
+
+            let last = key.len().saturating_sub(1);
+            let unmatchable = key.iter().enumerate().any(|(i, s)| {
+                (s.is_empty() && !(self.exact_paths && i == last)) || *s == "." || *s == ".."
+            });
+            if unmatchable {
+                problems.push(format!("{}: route contains an empty, `.` or `..` segment, so can \
+                                       never match a request", route));
+            }
+
+            // Matches for different hosts are searched separately, so can't shadow each other.
+            let mut ranked: Vec<&Match> = matches.iter().collect();
+            ranked.sort_by(|a, b| b.priority.cmp(&a.priority));
+            for (i, matched) in ranked.iter().enumerate() {
+                let shadowed = ranked[..i].iter().any(|earlier| {
+                    earlier.host == matched.host && earlier.accepts_any_for_host() &&
+                        (!earlier.exact || matched.exact) && earlier.fold_case == matched.fold_case
+                });
+                if shadowed {
+                    problems.push(format!("{}: handler is unreachable, as a handler on the same \
+                                           route with a higher priority accepts all of its \
+                                           requests", route));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            problems.sort();
+            Err(problems)
+        }
+    }
+
+    /// Returns an iterator over the mounted handlers, along with their routes.
+    ///
+    /// Routes are formatted as by `routes`. The fallback handler is not included. Together
+    /// with `from_routes` this allows a routing table to be rebuilt with every handler
+    /// wrapped, for example in logging or authentication handlers.
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter {
+            inner: self.inner.iter(),
+            current: None
+        }
+    }
+
+    /// Returns a read-only view of the routing tree, starting from the root route `/`.
+    pub fn tree<'a>(&'a self) -> MountNode<'a> {
+        MountNode { inner: &self.inner }
+    }
+
+    /// Returns the number of requests dispatched to the handlers on each mounted route.
+    ///
+    /// Requests are only counted while enabled with `Mount::count_requests`; the counts for
+    /// handlers scoped to a host or method are included in the count for their route.
+    /// Requests passed to the fallback handler are not counted.
+    pub fn stats(&self) -> HashMap<String, u64> {
+        self.inner.iter().map(|(key, matches)| {
+            let count = matches.iter()
+                .fold(0, |count, matched| count + matched.hits.load(Ordering::Relaxed));
+            (format_route(&key), count)
+        }).collect()
+    }
+
+    /// Returns a listing of the mounted handlers, one per line, for logging the routing table.
+    ///
+    /// Each line gives the method the handler is mounted for, or `*` for any method, then
+    /// any host and the route, followed by any other restrictions and options in brackets,
+    /// for example `GET example.com/users/:id (priority 1)`. The lines are sorted, so the
+    /// listing is stable across runs and suitable for comparing in tests. Subtree defaults
+    /// are listed as `* /api (default)`, and fallback handlers are listed last, each as
+    /// `* (fallback)`.
+    pub fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        for (key, matches) in self.inner.iter() {
+            let route = format_route(&key);
+            lines.extend(matches.iter().map(|matched| matched.describe(&route)));
+        }
+        lines.extend(self.defaults.keys().map(|key| format!("* {} (default)", format_route(&key))));
+        lines.sort();
+        lines.extend(self.fallbacks.iter().map(|_| "* (fallback)".to_string()));
+        lines.iter().fold(String::new(), |listing, line| listing + line + "\n")
+    }
+}
+
+/// An iterator over the routes of a `Mount`, created by `Mount::routes`.
+///
+/// A route with several handlers mounted on it, for example for different hosts, is
+/// only yielded once.
+pub struct Routes<'a> {
+    inner: sequence_trie::Keys<'a, String, Vec<Match>>
+}
+
+impl<'a> Iterator for Routes<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.inner.next().map(|key| format_route(&key))
+    }
+}
+
+/// A read-only view of a node in the routing tree of a `Mount`, created by `Mount::tree`.
+///
+/// Each node stands for a route, and its children extend that route by one segment. Nodes
+/// may be intermediate, with no handler mounted on their route, or terminal.
+#[derive(Clone, Copy)]
+pub struct MountNode<'a> {
+    inner: &'a SequenceTrie<String, Vec<Match>>
+}
+
+impl<'a> MountNode<'a> {
+    /// Returns `true` if a handler is mounted on this node's route.
+    pub fn has_handler(&self) -> bool {
+        self.inner.value.is_some()
+    }
+
+    /// Returns the number of handlers mounted on this node's route, for example for
+    /// different hosts or methods.
+    pub fn handler_count(&self) -> usize {
+        self.inner.value.as_ref().map_or(0, |matches| matches.len())
+    }
+
+    /// Returns the children of this node, each with the segment leading to it, sorted by
+    /// segment.
+    pub fn children(&self) -> Vec<(&'a str, MountNode<'a>)> {
+        let mut children: Vec<(&'a str, MountNode<'a>)> = self.inner.children.iter()
+            .map(|(segment, child)| (&**segment, MountNode { inner: child }))
+            .collect();
+        children.sort_by(|a, b| a.0.cmp(b.0));
+        children
+    }
+}
+
+/// An iterator over the routes and handlers of a `Mount`, created by `Mount::iter`.
+///
+/// A route with several handlers mounted on it, for example for different hosts, is
+/// yielded once for each handler.
+pub struct Iter<'a> {
+    inner: sequence_trie::Iter<'a, String, Vec<Match>>,
+    current: Option<(String, slice::Iter<'a, Match>)>
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (String, &'a Handler);
+
+    fn next(&mut self) -> Option<(String, &'a Handler)> {
+        loop {
+            if let Some((ref route, ref mut matches)) = self.current {
+                if let Some(matched) = matches.next() {
+                    return Some((route.clone(), &*matched.handler));
+                }
+            }
+            match self.inner.next() {
+                Some((key, matches)) => self.current = Some((format_route(&key), matches.iter())),
+                None => return None
+            }
+        }
+    }
+}
+
+impl Extend<(String, Box<Handler>)> for Mount {
+    fn extend<I: IntoIterator<Item=(String, Box<Handler>)>>(&mut self, routes: I) {
+        for (route, handler) in routes {
+            self.mount(route, handler);
+        }
+    }
+}
+
+impl Default for Mount {
+    fn default() -> Mount {
+        Mount::new()
+    }
+}
+
+impl fmt::Debug for Mount {
+    // Handlers can't be printed, so list each route along with its length instead.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut routes: Vec<(String, usize)> = self.inner.iter()
+            .map(|(key, matches)| (format_route(&key), matches[0].length))
+            .collect();
+        routes.sort();
+
+        try!(f.write_str("Mount { routes: ["));
+        for (i, &(ref route, length)) in routes.iter().enumerate() {
+            if i > 0 { try!(f.write_str(", ")); }
+            try!(write!(f, "{:?} (len {})", route, length));
+        }
+        f.write_str("] }")
+    }
+}
+
+impl Mount {
+    // Parse a route which a handler is about to be mounted on, rejecting an empty route
+    // rather than mounting a catch-all.
+    fn mount_key(&self, route: &str) -> Vec<String> {
+        if route.trim().is_empty() {
+            panic!("Route {:?} is empty; use \"/\" to mount on the root.", route);
+        }
+        self.route_key(route)
+    }
+
+    // Parse a route which is looked up rather than mounted on. An empty route names no
+    // route at all, rather than the root.
+    fn existing_key(&self, route: &str) -> Option<Vec<String>> {
+        if route.trim().is_empty() { None } else { Some(self.route_key(route)) }
+    }
+
+    // Parse a route into the key it is stored under in the trie. The names of wildcard
+    // segments are never folded.
+    fn route_key(&self, route: &str) -> Vec<String> {
+        if self.strict {
+            let trimmed = if route.ends_with('/') { &route[..route.len() - 1] } else { route };
+            let segments: Vec<&str> = trimmed.split('/').collect();
+            if segments.len() > 1 && segments[1..].iter().any(|s| s.is_empty()) {
+                panic!("Route {:?} contains an empty segment.", route);
+            }
+        }
+
+        let mut key: Vec<String> = parse_route(route).into_iter()
+            .map(|segment| self.fold_segment(segment))
+            .collect();
+
+        // With exact paths, a trailing slash is kept as a final empty segment.
+        if self.exact_paths && route.ends_with('/') && !key.is_empty() {
+            key.push(String::new());
+        }
+        key
+    }
+
+    // Fold a route segment in the same way request path segments are folded for matching.
+    fn fold_segment(&self, segment: String) -> String {
+        let segment = normalize(segment);
+        if self.case_insensitive { fold_case(segment) } else { segment }
+    }
+
+    // The error for a request which doesn't match any handler.
+    fn no_match_error(&self, error: MountError) -> IronError {
+        match self.no_match {
+            Some(ref no_match) => IronError {
+                error: Box::new(error),
+                response: no_match()
+            },
+            None => IronError::new(error, status::NotFound)
+        }
+    }
+
+    // Convert the segments of a request path into the key to search the trie for.
+    //
+    // Segments are percent-decoded, and their case and Unicode normal form are folded in the
+    // same way routes were folded when mounted. Only the key used for matching is changed,
+    // so the number of segments stripped from the request's path is unaffected.
+    //
+    // Case is also folded when `fold_case` is set, for matching routes mounted with `mount_ci`.
+    fn path_key<'p>(&self, path: &'p [String], fold_case: bool) -> Cow<'p, [String]> {
+        let fold_case = fold_case || self.case_insensitive;
+        if !fold_case && !path.iter().any(|segment| segment.contains('%')) {
+            return Cow::Borrowed(path);
+        }
+
+        Cow::Owned(path.iter().map(|segment| {
+            let segment = normalize(decode_segment(segment));
+            if fold_case { segment.to_lowercase() } else { segment }
+        }).collect())
+    }
+
+    // Insert a match into the trie, returning the match it replaced, if any.
+    fn insert(&mut self, key: &[String], mut matched: Match) -> Option<Match> {
+        matched.route = key.to_vec();
+        self.folded_routes |= matched.fold_case;
+        if let Some(matches) = self.inner.get_mut(key) {
+            if let Some(i) = matches.iter().position(|existing| existing.same_slot(&matched)) {
+                if let Some(ref on_overwrite) = self.on_overwrite {
+                    on_overwrite(&format_route(key));
+                }
+                let replaced = mem::replace(&mut matches[i], matched);
+                replaced.unmounted();
+                return Some(replaced);
+            }
+
+            // Keep the matches ordered by specificity, and otherwise by insertion.
+            let i = matches.iter()
+                .position(|existing| existing.specificity() < matched.specificity())
+                .unwrap_or(matches.len());
+            matches.insert(i, matched);
+            return None;
+        }
+        self.inner.insert(key, vec![matched]);
+        None
+    }
+
+    // Insert a match taken from another mount on a key beneath a prefix, folding the key as
+    // routes mounted here are folded.
+    fn insert_under(&mut self, prefix_key: &[String], key: &[String], matched: Match) {
+        let mut full_key = prefix_key.to_vec();
+        full_key.extend(key.iter().map(|segment| {
+            let segment = self.fold_segment(segment.clone());
+            if matched.fold_case { fold_case(segment) } else { segment }
+        }));
+        self.insert(&full_key, Match { length: full_key.len(), ..matched });
+    }
+
+    // Remove every match from the trie, along with the key of its route.
+    fn take_routes(&mut self) -> Vec<(Vec<String>, Vec<Match>)> {
+        let keys: Vec<Vec<String>> = self.inner.keys()
+            .map(|key| key.into_iter().cloned().collect())
+            .collect();
+        let routes = keys.into_iter().filter_map(|key| {
+            let matches = self.inner.get_mut_node(&key).and_then(|node| node.value.take());
+            matches.map(|matches| (key, matches))
+        }).collect();
+        self.inner = SequenceTrie::new();
+        routes
+    }
+
+    // Find the plain match on exactly this key.
+    fn plain_match(&self, key: &[String]) -> Option<&Match> {
+        self.inner.get(key).and_then(|matches| matches.iter().find(|m| m.is_plain()))
+    }
+
+    // Remove the plain match on exactly this key, pruning any nodes which no longer lead
+    // to a handler.
+    fn remove_plain(&mut self, key: &[String]) -> Option<Match> {
+        let (removed, is_empty) = match self.inner.get_mut(key) {
+            Some(matches) => match matches.iter().position(|m| m.is_plain()) {
+                Some(i) => (matches.remove(i), matches.is_empty()),
+                None => return None
+            },
+            None => return None
+        };
+        if is_empty { self.inner.remove(key); }
+        Some(removed)
+    }
+
+    // The error for a request path exceeding a limit set with `max_depth` or
+    // `max_segment_len`, if it does.
+    fn exceeded_limit(&self, path: &[String]) -> Option<MountError> {
+        if let Some(max_depth) = self.max_depth {
+            if path.iter().filter(|segment| !segment.is_empty()).count() > max_depth {
+                return Some(MountError::PathTooLong);
+            }
+        }
+        if let Some(max_len) = self.max_segment_len {
+            if path.iter().any(|segment| segment.len() > max_len) {
+                return Some(MountError::SegmentTooLong);
+            }
+        }
+        None
+    }
+
+    // The number of segments of a request's path holding the prefixes stripped before
+    // matching, if the path starts with them: any prefix given by the `X-Forwarded-Prefix`
+    // header, followed by the global prefix. Segments are compared in the same way as when
+    // matching routes.
+    fn prefix_length(&self, req: &Request) -> Option<usize> {
+        let forwarded = if self.strip_forwarded_prefix {
+            req.headers.get_raw(FORWARDED_PREFIX)
+                .and_then(|values| values.first())
+                .and_then(|value| String::from_utf8(value.clone()).ok())
+                .map_or(Vec::new(), |prefix| {
+                    parse_route(&prefix).into_iter()
+                        .map(|segment| self.fold_segment(segment))
+                        .collect()
+                })
+        } else {
+            Vec::new()
+        };
+        let prefix: Vec<&String> = forwarded.iter().chain(&self.global_prefix).collect();
+        self.strip_length(&req.url.path, &prefix)
+    }
+
+    // The number of segments of a path holding the global prefix, if the path starts with it.
+    fn global_prefix_length(&self, path: &[String]) -> Option<usize> {
+        let prefix: Vec<&String> = self.global_prefix.iter().collect();
+        self.strip_length(path, &prefix)
+    }
+
+    // The number of segments of a path holding a prefix, if the path starts with it.
+    fn strip_length(&self, path: &[String], prefix: &[&String]) -> Option<usize> {
+        if prefix.is_empty() { return Some(0); }
+
+        let key = self.path_key(path, false);
+        let mut segments = key.iter().filter(|segment| !segment.is_empty());
+        if prefix.iter().all(|&expected| segments.next() == Some(expected)) {
+            Some(raw_length(path, prefix.len()))
+        } else {
+            None
+        }
+    }
+
+    // The handlers to try in turn for an unmatched request path: the most specific subtree
+    // default, if any, followed by the fallbacks.
+    fn fallbacks_for(&self, path: &[String]) -> Vec<&Handler> {
+        let segments: Vec<String> = path.iter().filter(|s| !s.is_empty()).cloned().collect();
+        let key = self.path_key(&segments, false);
+        self.defaults.get_ancestor(&key).into_iter().chain(&self.fallbacks)
+            .map(|fallback| &**fallback)
+            .collect()
+    }
+
+    // The answer to an `OPTIONS` request, if `auto_options` answers it. Only handlers which
+    // serve the request's host are considered, and if all of those are disabled the request
+    // is refused as a request for a disabled route would be.
+    fn auto_options_response(&self, req: &Request, path: &[String])
+                             -> Option<IronResult<Response>> {
+        if !self.auto_options || req.method != Method::Options {
+            return None;
+        }
+        let host = req.headers.get::<headers::Host>().map(|host| host.hostname.to_lowercase());
+        let serves_host = |matched: &Match| match (matched.host.as_ref(), host.as_ref()) {
+            (None, _) => true,
+            (Some(pattern), Some(host)) => pattern == host || host_matches(pattern, host),
+            (Some(_), None) => false
+        };
+
+        let allowed = self.methods_for(path, |matched| matched.enabled && serves_host(matched));
+        if !allowed.is_empty() {
+            let mut res = Response::with(status::Ok);
+            res.headers.set(headers::Allow(allowed));
+            Some(Ok(res))
+        } else if !self.methods_for(path, &serves_host).is_empty() {
+            Some(Err(IronError::new(MountError::Disabled, self.disabled_status)))
+        } else {
+            None
+        }
+    }
+
+    // Find the match for a request, whose path, once any global prefix is stripped, is `path`.
+    fn find_match<'a>(&'a self, req: &Request, path: &[String]) -> Lookup<'a> {
+        let candidates = self.candidates(path);
+        let candidates = rank(&candidates);
+
+        // Prefer handlers mounted for the request's host, then those mounted for a wildcard
+        // matching it, then search host-agnostic ones.
+        let host = req.headers.get::<headers::Host>().map(|host| host.hostname.to_lowercase());
+        let no_route = MountError::NoRoute { path: String::new() };
+        let mut rejected = Rejection { error: no_route, allowed: Vec::new() };
+        let matched = match host {
+            Some(ref host) => {
+                search(&candidates, |h| h == Some(host), req, &mut rejected).or_else(|| {
+                    let wildcard = |h: Option<&String>| h.map_or(false, |h| host_matches(h, host));
+                    search(&candidates, wildcard, req, &mut rejected)
+                })
+            },
+            None => None
+        };
+        match matched.or_else(|| search(&candidates, |h| h.is_none(), req, &mut rejected)) {
+            Some((matched, consumed, params)) => Lookup::Matched(matched, consumed, params),
+            None => {
+                // The path is only formatted once it's known that no handler matched.
+                if let MountError::NoRoute { path: ref mut formatted } = rejected.error {
+                    *formatted = external_path(req, path);
+                }
+                Lookup::Unmatched(rejected)
+            }
+        }
+    }
+
+    // Find the routes matching a request path, from the most specific to the least.
+    fn candidates<'a>(&'a self, path: &[String]) -> Vec<Candidate<'a>> {
+        // Remove any empty segments, which represent trailing or repeated slashes. If they
+        // aren't removed the path will never match anything, because routes are parsed
+        // ignoring empty segments and never contain "". With exact
+        // paths, a single trailing empty segment is kept to match routes mounted with one.
+        let end = path.iter().rposition(|s| !s.is_empty()).map_or(0, |i| i + 1);
+        let trailing = self.exact_paths && end < path.len();
+        let segments: Cow<[String]> = if trailing || path[..end].iter().any(|s| s.is_empty()) {
+            let mut segments: Vec<String> = path[..end].iter()
+                .filter(|s| !s.is_empty())
+                .cloned()
+                .collect();
+            if trailing { segments.push(String::new()); }
+            Cow::Owned(segments)
+        } else {
+            Cow::Borrowed(&path[..end])
+        };
+
+        // The raw segments are used for the values of captured params, so that they aren't
+        // affected by case folding. Unless every route is matched folded, routes mounted with
+        // `mount_ci` are found by searching again with the path folded.
+        let passes: &[Option<bool>] = if self.case_insensitive || !self.folded_routes {
+            &[None]
+        } else {
+            &[Some(false), Some(true)]
+        };
+        let mut found = Vec::new();
+        for &pass in passes {
+            let key = self.path_key(&segments, pass.unwrap_or(false));
+            let start = found.len();
+            collect_candidates(&self.inner, &key, &segments, 0, &mut Vec::new(), &mut Vec::new(),
+                               &mut found);
+            for &mut (_, ref mut candidate) in &mut found[start..] {
+                candidate.fold_case = pass;
+            }
+        }
+
+        // Nodes are visited with static segments before wildcards, so a stable sort keeps
+        // static routes ahead of wildcard routes of the same length, and routes matched
+        // literally ahead of those matched folded.
+        found.sort_by(|a, b| b.0.cmp(&a.0));
+        found.into_iter().map(|(depth, mut candidate)| {
+            candidate.consumed = raw_length(path, depth);
+            candidate
+        }).collect()
+    }
+}
+
+// Walk the trie along a key, collecting every node with matches and the depth it was found
+// at. Static children are visited before wildcards.
+fn collect_candidates<'a>(node: &'a SequenceTrie<String, Vec<Match>>, key: &[String],
+                          path: &[String], depth: usize, route: &mut Vec<&'a String>,
+                          params: &mut Vec<(String, String)>,
+                          found: &mut Vec<(usize, Candidate<'a>)>) {
+    if let Some(ref matches) = node.value {
+        found.push((depth, Candidate {
+            route: route.clone(),
+            consumed: depth,
+            complete: depth == key.len(),
+            fold_case: None,
+            matches: matches,
+            params: params.clone()
+        }));
+    }
+
+    let segment = match key.get(depth) {
+        Some(segment) => segment,
+        None => return
+    };
+
+    if !is_wildcard(segment) {
+        if let Some((fragment, child)) = node.children.get_key_value(segment) {
+            route.push(fragment);
+            collect_candidates(child, key, path, depth + 1, route, params, found);
+            route.pop();
+        }
+    }
+
+    if segment.is_empty() { return; }
+
+    // Visit wildcards in a consistent order, so that matching is deterministic.
+    let mut wildcards: Vec<(&String, &SequenceTrie<String, Vec<Match>>)> = node.children.iter()
+        .filter(|&(fragment, _)| is_wildcard(fragment))
+        .collect();
+    wildcards.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (fragment, child) in wildcards {
+        route.push(fragment);
+        params.push((fragment[1..].to_string(), decode_segment(&path[depth])));
+        collect_candidates(child, key, path, depth + 1, route, params, found);
+        params.pop();
+        route.pop();
+    }
+}
+
+// The number of segments of a raw path which hold its first `depth` non-empty segments.
+fn raw_length(path: &[String], depth: usize) -> usize {
+    if depth == 0 { return 0; }
+    path.iter().enumerate()
+        .filter(|&(_, segment)| !segment.is_empty())
+        .nth(depth - 1)
+        .map_or(path.len(), |(i, _)| i + 1)
+}
+
+// The header used to tell mounted handlers the stripped prefix.
+const FORWARDED_PREFIX: &'static str = "X-Forwarded-Prefix";
+
+// Search the candidates for a request, most specific first, for a match mounted for `host`
+// which accepts the request. Records why matches were rejected, with a wrong method taking
+// precedence over an unacceptable media type, and that over a declining predicate.
+//
+// Of the matches for media types on the first route with an acceptable one, the one the
+// request gives the highest quality is chosen, ahead of any other matches on the route.
+fn search<'a, F>(candidates: &[(&Candidate<'a>, &'a Match)], accepts_host: F,
+                 req: &Request, rejected: &mut Rejection)
+                 -> Option<(&'a Match, usize, Vec<(String, String)>)>
+where F: Fn(Option<&String>) -> bool {
+    let applicable = candidates.iter()
+        .filter(|&&(candidate, m)| accepts_host(m.host.as_ref()) && m.accepts_path(candidate));
+    let mut negotiated: Option<(u16, &Candidate<'a>, &'a Match)> = None;
+    for &(candidate, matched) in applicable {
+        if let Some((_, best, _)) = negotiated {
+            if !ptr::eq(best, candidate) { break; }
+        }
+        if let Some(ref pair) = matched.query {
+            if !has_query_pair(req, pair) { continue; }
+        }
+        if let Some(ref predicate) = matched.predicate {
+            if !predicate(req) {
+                if let MountError::NoRoute { .. } = rejected.error {
+                    rejected.error = MountError::Declined;
+                }
+                continue;
+            }
+        }
+        if let Some(ref method) = matched.method {
+            if *method != req.method {
+                rejected.error = MountError::MethodNotAllowed;
+                if !rejected.allowed.contains(method) {
+                    rejected.allowed.push(method.clone());
+                }
+                continue;
+            }
+        }
+        if let Some(ref media_type) = matched.media_type {
+            let quality = accept_quality(req, media_type);
+            if quality == 0 {
+                match rejected.error {
+                    MountError::NoRoute { .. } | MountError::Declined => {
+                        rejected.error = MountError::NotAcceptable;
+                    },
+                    _ => {}
+                }
+            } else if negotiated.map_or(true, |(best, _, _)| quality > best) {
+                negotiated = Some((quality, candidate, matched));
+            }
+            continue;
+        }
+        if negotiated.is_some() { break; }
+        return Some((matched, candidate.consumed, candidate.params.clone()));
+    }
+    negotiated.map(|(_, candidate, m)| (m, candidate.consumed, candidate.params.clone()))
+}
+
+// The quality, out of 1000, a request's `Accept` header gives a media type, taken from the
+// most specific range matching it. Without the header, every media type is acceptable.
+//
+// The header is parsed here rather than with `headers::Accept`, which drops wildcard ranges.
+fn accept_quality(req: &Request, media_type: &(String, String)) -> u16 {
+    let values = match req.headers.get_raw("Accept") {
+        Some(values) => values,
+        None => return 1000
+    };
+    let (ref top, ref sub) = *media_type;
+    let ranges = values.iter()
+        .filter_map(|value| String::from_utf8(value.clone()).ok())
+        .flat_map(|value| value.split(',').map(str::to_lowercase).collect::<Vec<_>>())
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let mut types = parts.next().unwrap_or("").splitn(2, '/');
+            let specificity = match (types.next(), types.next()) {
+                (Some("*"), Some("*")) => 0,
+                (Some(range_top), Some("*")) if range_top == top => 1,
+                (Some(range_top), Some(range_sub)) if range_top == top && range_sub == sub => 2,
+                _ => return None
+            };
+            let quality = parts.filter(|param| param.starts_with("q="))
+                .filter_map(|param| param[2..].parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+            Some((specificity, (quality.max(0.0).min(1.0) * 1000.0).round() as u16))
+        });
+    ranges.fold(None, |best: Option<(u8, u16)>, range| match best {
+        Some(best) if best.0 >= range.0 => Some(best),
+        _ => Some(range)
+    }).map_or(0, |(_, quality)| quality)
+}
+
+// Order every match on the candidate routes by the depth of its route, and then by its
+// priority. Otherwise the order of the candidates, and of the matches on each, is kept.
+fn rank<'c, 'a>(candidates: &'c [Candidate<'a>]) -> Vec<(&'c Candidate<'a>, &'a Match)> {
+    let mut ranked: Vec<(&'c Candidate<'a>, &'a Match)> = candidates.iter()
+        .flat_map(|candidate| candidate.matches.iter().map(move |m| (candidate, m)))
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.0.route.len().cmp(&a.0.route.len()).then(b.1.priority.cmp(&a.1.priority))
+    });
+    ranked
+}
+
+// Whether a request's query string contains a key and value.
+fn has_query_pair(req: &Request, pair: &(String, String)) -> bool {
+    match req.url.query {
+        Some(ref query) => form_urlencoded::parse(query.as_bytes()).iter().any(|p| p == pair),
+        None => false
+    }
+}
+
+// Lowercase a route segment, unless it is a wildcard, whose name is never folded.
+fn fold_case(segment: String) -> String {
+    if is_wildcard(&segment) { segment } else { segment.to_lowercase() }
+}
+
+// Whether a route segment is a wildcard, of the form `:name`.
+fn is_wildcard(segment: &str) -> bool {
+    segment.len() > 1 && segment.starts_with(':')
+}
+
+// Redirect a request to the same URL with a trailing slash added. If an enclosing mount
+// has already stripped part of the path, the original URL is used.
+fn trailing_slash_redirect(req: &Request) -> Response {
+    let mut url = match req.extensions.get::<OriginalUrl>() {
+        Some(original) => original.clone(),
+        None => req.url.clone()
+    };
+    url.path.push(String::new());
+    Response::with((status::MovedPermanently, Redirect(url)))
+}
+
+// Lowercase a host name and strip any port from it.
+fn normalize_host(host: &str) -> String {
+    let host = host.to_lowercase();
+    match host.rfind(':') {
+        Some(i) if !host.ends_with(']') && host[i + 1..].chars().all(|c| c.is_digit(10)) => {
+            host[..i].to_string()
+        },
+        _ => host
+    }
+}
+
+// Whether a host name matches a pattern of the form `*.example.com`, which matches
+// `api.example.com` but neither `example.com` nor `a.b.example.com`.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    if !pattern.starts_with("*.") { return false; }
+    let suffix = &pattern[1..];
+    host.ends_with(suffix) && {
+        let label = &host[..host.len() - suffix.len()];
+        !label.is_empty() && !label.contains('.')
+    }
+}
+
+// Percent-decode a path segment. Segments which don't decode to valid UTF-8 are left as
+// they are.
+fn decode_segment(segment: &str) -> String {
+    match String::from_utf8(percent_decode(segment.as_bytes())) {
+        Ok(decoded) => decoded,
+        Err(_) => segment.to_string()
+    }
+}
+
+// Convert a decoded segment to Unicode Normalization Form C, so that canonically
+// equivalent segments, such as those with precomposed and decomposed accents, are equal.
+#[cfg(feature = "unicode-normalization")]
+fn normalize(segment: String) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    if segment.bytes().all(|b| b < 0x80) { return segment; }
+    segment.nfc().collect()
+}
+
+// Without the `unicode-normalization` feature, segments are matched exactly as decoded.
+#[cfg(not(feature = "unicode-normalization"))]
+fn normalize(segment: String) -> String {
+    segment
+}
+
+// Format a list of route segments as a route string with a leading slash.
+fn format_route<S: AsRef<str>>(key: &[S]) -> String {
+    let mut route = String::new();
+    for segment in key {
+        route.push('/');
+        route.push_str(segment.as_ref());
+    }
+    if route.is_empty() { route.push('/'); }
+    route
+}
+
+// Format a request path as it was requested, before any enclosing mounts stripped their
+// prefixes or rewrote them, so that a nested mount's errors name the whole path. Outside of
+// a mount, `path` is the request's path.
+fn external_path(req: &Request, path: &[String]) -> String {
+    match req.extensions.get::<OriginalUrl>() {
+        Some(original) => format_route(&original.path),
+        None => format_route(path)
+    }
+}
+
+// Parse a route into a list of strings, splitting it on `/` and ignoring empty segments.
+// Routes are URL paths, so they are parsed the same way on every platform, without any of
+// the meaning `std::path` gives to backslashes or drive prefixes on Windows.
+//
+// `.` and `..` segments are resolved, since request paths never contain them. As with
+// URLs, a `..` at the root is dropped rather than escaping it.
+fn parse_route(route: &str) -> Vec<String> {
+    let mut key = Vec::new();
+    for segment in route.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => { key.pop(); },
+            segment => key.push(segment.to_string())
+        }
+    }
+    key
+}
+
+impl Handler for Mount {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        if let Some(error) = self.exceeded_limit(&req.url.path) {
+            return Err(IronError::new(error, self.limit_status));
+        }
+
+        let length = match self.prefix_length(req) {
+            Some(0) => return self.route_request(req),
+            Some(length) => length,
+            None => {
+                let path = external_path(req, &req.url.path);
+                return Err(self.no_match_error(MountError::NoRoute { path: path }));
+            }
+        };
+
+        // Strip the prefix as though it were the route of an enclosing mount, so that
+        // it is recorded in `MountedPath` and `OriginalUrl` is the URL as it was received.
+        let is_outer_mount = !req.extensions.contains::<OriginalUrl>();
+        let outer_original = if is_outer_mount {
+            Some(req.extensions.insert::<OriginalUrl>(req.url.clone()))
+        } else {
+            None
+        };
+        let outer_prefix = req.extensions.remove::<MountedPath>();
+        let mut prefix = outer_prefix.clone().unwrap_or_else(Vec::new);
+        let stripped: Vec<String> = req.url.path.drain(..length).collect();
+        prefix.extend(stripped.iter().filter(|s| !s.is_empty()).cloned());
+        req.extensions.insert::<MountedPath>(prefix);
+
+        let guard = Restore {
+            req: req,
+            stripped: stripped,
+            rewrite: &[],
+            rooted: false,
+            outer_original: outer_original,
+            outer_prefix: outer_prefix,
+            outer_params: None,
+            outer_forwarded: None
+        };
+        self.route_request(&mut *guard.req)
+    }
+}
+
+impl Mount {
+    // Dispatch a request to the matching handler, once any global prefix has been stripped.
+    fn route_request(&self, req: &mut Request) -> IronResult<Response> {
+        if let Some(answer) = self.auto_options_response(req, &req.url.path) {
+            return answer;
+        }
+
+        // Find the matching handler, falling back to the fallback handlers (which strip
+        // nothing) in turn if there are any.
+        match self.find_match(req, &req.url.path) {
+            Lookup::Matched(matched, consumed, params) => {
+                if !matched.enabled {
+                    return Err(IronError::new(MountError::Disabled, self.disabled_status));
+                }
+                // A path with a trailing slash always has more segments than the route.
+                if self.redirect_trailing_slash && !self.exact_paths &&
+                        consumed == req.url.path.len() {
+                    return Ok(trailing_slash_redirect(req));
+                }
+                if self.count_requests {
+                    matched.hits.fetch_add(1, Ordering::Relaxed);
+                }
+                let length = if matched.strip { consumed } else { 0 };
+                self.dispatch(req, &*matched.handler, length, Some(matched), params)
+            },
+            Lookup::Unmatched(Rejection { error: MountError::MethodNotAllowed, allowed }) => {
+                let mut err = IronError::new(MountError::MethodNotAllowed,
+                                             status::MethodNotAllowed);
+                err.response.headers.set(headers::Allow(allowed));
+                Err(err)
+            },
+            Lookup::Unmatched(Rejection { error: MountError::NotAcceptable, .. }) => {
+                Err(IronError::new(MountError::NotAcceptable, status::NotAcceptable))
+            },
+            Lookup::Unmatched(rejected) => {
+                for fallback in self.fallbacks_for(&req.url.path) {
+                    match self.dispatch(req, fallback, 0, None, Vec::new()) {
+                        Err(ref err) if err.error.downcast::<MountError>() ==
+                                        Some(&MountError::NotHandled) => continue,
+                        result => return result
+                    }
+                }
+                Err(self.no_match_error(rejected.error))
+            }
+        }
+    }
+
+    // Dispatch a request to a handler, stripping `length` segments from its path and passing
+    // on any params captured while matching it.
+    fn dispatch(&self, req: &mut Request, handler: &Handler, length: usize,
+                matched: Option<&Match>, params: Vec<(String, String)>) -> IronResult<Response> {
+
+        // A handler mounted on the root of the outermost mount sees the request exactly as it
+        // was received, so there is nothing to record or restore and it is called directly.
+        let rewrite = matched.map_or(&[][..], |matched| &matched.rewrite[..]);
+        let rooted = matched.map_or(false, |matched| matched.route.is_empty());
+        if rooted && length == 0 && params.is_empty() && rewrite.is_empty() &&
+                !self.forward_prefix && !req.url.path.is_empty() &&
+                !req.extensions.contains::<OriginalUrl>() &&
+                !req.extensions.contains::<MountedPath>() {
+            if let (Some(matched), Some(on_dispatch)) = (matched, self.on_dispatch.as_ref()) {
+                on_dispatch(&matched.route, req);
+            }
+            return handler.handle(req);
+        }
+
+        // We have a match, so fire off the child.
+        // If another mount middleware hasn't already, insert the unmodified url
+        // into the extensions as the "original url". When resetting it per level, the url
+        // this mount received replaces any set by enclosing mounts, keeping theirs to restore.
+        let is_outer_mount = !req.extensions.contains::<OriginalUrl>();
+        let outer_original = if is_outer_mount || self.reset_original_url {
+            Some(req.extensions.insert::<OriginalUrl>(req.url.clone()))
+        } else {
+            None
+        };
+
+        // Record the prefix being stripped, on top of any prefix stripped by enclosing mounts.
+        // Empty segments stripped along with the route, such as the trailing slash of a route
+        // mounted with `exact_paths`, are left out so that the prefix joins up cleanly.
+        let outer_prefix = req.extensions.remove::<MountedPath>();
+        let mut prefix = outer_prefix.clone().unwrap_or_else(Vec::new);
+        prefix.extend(req.url.path[..length].iter().filter(|s| !s.is_empty()).cloned());
+
+        // Pass the prefix on in a header, if enabled, keeping any value it replaces. The
+        // header is left out while nothing has been stripped.
+        let outer_forwarded = if self.forward_prefix {
+            let outer_forwarded = req.headers.get_raw(FORWARDED_PREFIX).map(|v| v.to_vec());
+            let value = prefix.iter().fold(String::new(), |value, segment| value + "/" + segment);
+            if value.is_empty() {
+                req.headers.remove_raw(FORWARDED_PREFIX);
+            } else {
+                req.headers.set_raw(FORWARDED_PREFIX, vec![value.into_bytes()]);
+            }
+            Some(outer_forwarded)
+        } else {
+            None
+        };
+        req.extensions.insert::<MountedPath>(prefix);
+
+        // Add any captured params to those captured by enclosing mounts.
+        let outer_params = if params.is_empty() {
+            None
+        } else {
+            let outer_params = req.extensions.remove::<Params>();
+            let mut all_params = outer_params.clone().unwrap_or_else(HashMap::new);
+            all_params.extend(params);
+            req.extensions.insert::<Params>(all_params);
+            Some(outer_params)
+        };
+
+        // Remove the prefix from the request's path before passing it to the mounted handler.
+        // If the prefix is entirely removed and no trailing slash was present, the new path
+        // will be the empty list. For the purposes of redirection, conveying that the path
+        // did not include a trailing slash is more important than providing a non-empty list.
+        //
+        // The prefix is moved out of the path in place rather than copying the rest of it, so
+        // that the path keeps its allocation and the prefix can be restored into it.
+        let stripped: Vec<String> = req.url.path.drain(..length).collect();
+        req.url.path.splice(..0, rewrite.iter().cloned());
+        let rooted = self.root_empty_path && req.url.path.is_empty();
+        if rooted {
+            req.url.path.push(String::new());
+        }
+
+        // Everything changed above is put back when the guard is dropped, which happens even
+        // if the handler panics.
+        let guard = Restore {
+            req: req,
+            stripped: stripped,
+            rewrite: rewrite,
+            rooted: rooted,
+            outer_original: outer_original,
+            outer_prefix: outer_prefix,
+            outer_params: outer_params,
+            outer_forwarded: outer_forwarded
+        };
+
+        if let (Some(matched), Some(on_dispatch)) = (matched, self.on_dispatch.as_ref()) {
+            on_dispatch(&matched.route, &*guard.req);
+        }
+        handler.handle(&mut *guard.req)
+    }
+}
+
+// Restores a request to how a mount received it once its handler returns, or panics, so
+// that enclosing middleware which catches the panic sees the request as it was.
+//
+// The guard is created as soon as a mount changes a request, and records every extension key
+// the mount set, so that no early return after that point can leak them. Requests refused
+// before a handler is chosen are returned before anything is changed.
+struct Restore<'r, 'a: 'r, 'b: 'a> {
+    req: &'r mut Request<'a, 'b>,
+    // The prefix stripped from the path.
+    stripped: Vec<String>,
+    // The segments the prefix was rewritten to.
+    rewrite: &'r [String],
+    // Whether an empty segment was added to an otherwise empty path.
+    rooted: bool,
+    // The values replaced by the mount, which are only set if the mount changed them. The
+    // inner `None` means there was no value before.
+    outer_original: Option<Option<Url>>,
+    outer_prefix: Option<Vec<String>>,
+    outer_params: Option<Option<HashMap<String, String>>>,
+    outer_forwarded: Option<Option<Vec<Vec<u8>>>>
+}
+
+impl<'r, 'a, 'b> Drop for Restore<'r, 'a, 'b> {
+    fn drop(&mut self) {
+        let req = &mut *self.req;
+
+        // Remove the segment added for an empty path, unless the handler replaced it.
+        if self.rooted && req.url.path.len() == 1 && req.url.path[0].is_empty() {
+            req.url.path.clear();
+        }
+
+        // Remove the segments the prefix was rewritten to, unless the handler replaced them.
+        if !self.rewrite.is_empty() && req.url.path.starts_with(self.rewrite) {
+            req.url.path.drain(..self.rewrite.len());
+        }
+
+        // Reverse the URL munging, for future middleware. Only the stripped prefix is
+        // restored, so any other changes the handler made to the URL (such as to the query)
+        // are seen by enclosing middleware.
+        req.url.path.splice(..0, self.stripped.drain(..));
+
+        // If this mount middleware is the outermost mount middleware,
+        // remove the original url from the extensions map to prevent leakage. Otherwise,
+        // if this mount replaced it, restore the one set by the enclosing mount.
+        match self.outer_original.take() {
+            Some(Some(outer_original)) => { req.extensions.insert::<OriginalUrl>(outer_original); },
+            Some(None) => { req.extensions.remove::<OriginalUrl>(); },
+            None => ()
+        }
+
+        // Restore the prefix seen by enclosing mounts, if any.
+        match self.outer_prefix.take() {
+            Some(outer_prefix) => { req.extensions.insert::<MountedPath>(outer_prefix); },
+            None => { req.extensions.remove::<MountedPath>(); }
+        }
+
+        // Likewise for params, if any were captured.
+        match self.outer_params.take() {
+            Some(Some(outer_params)) => { req.extensions.insert::<Params>(outer_params); },
+            Some(None) => { req.extensions.remove::<Params>(); },
+            None => ()
+        }
+
+        // And for the forwarded prefix header, if it was set.
+        match self.outer_forwarded.take() {
+            Some(Some(outer_forwarded)) => req.headers.set_raw(FORWARDED_PREFIX, outer_forwarded),
+            Some(None) => { req.headers.remove_raw(FORWARDED_PREFIX); },
+            None => ()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {    
+    use super::{Mount, MatchInfo, MountError, MountedHandler, MountedPath, OriginalUrl, Params,
+                current_prefix, mount_aware_redirect};
+    use iron::{Request, Response, IronResult, IronError, Url, headers, status};
+    use iron::response::ResponseBody;
+    use hyper::method::Method;
+    use hyper::buffer::BufReader;
+    use hyper::net::NetworkStream;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::panic;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use iron::middleware::Handler;
+
+    fn send_hello(_: &mut Request) -> IronResult<Response> {
+        Ok(Response::new())
+    }
+
+    #[test]
+    fn it_mounts() {
+        let mut mount = Mount::new();
+        mount.mount("/testing", send_hello);
+        mount.mount("/another/file.png", send_hello);
+        assert!(test_url_matches(&mount, "http://localhost/testing"));
+        assert!(test_url_matches(&mount, "http://localhost/testing/"));
+        assert!(test_url_matches(&mount, "http://localhost/testing/something"));
+        assert!(! test_url_matches(&mount, "http://localhost/test"));
+        assert!(! test_url_matches(&mount, "http://localhost/test/testing"));
+        assert!(test_url_matches(&mount, "http://localhost/another/file.png"));
+        assert!(! test_url_matches(&mount, "http://localhost/another/file.jpg"));
+    }
+
+    #[test]
+    fn it_ignores_empty_segments() {
+        let mut mount = Mount::new();
+        mount.mount("/api", Named("api"));
+        mount.mount("/api/users", Named("users"));
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/api/"), r#"api [""]"#);
+        assert_eq!(body("http://localhost/api//"), r#"api ["", ""]"#);
+        assert_eq!(body("http://localhost/api//users"), r#"users []"#);
+        assert_eq!(body("http://localhost//api/users//1"), r#"users ["", "1"]"#);
+    }
+
+    #[test]
+    fn it_mounts_at_the_root() {
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root"));
+
+        let body = |mount: &Mount, url| response_body(dispatch(mount, url).unwrap());
+        assert_eq!(body(&mount, "http://localhost/"), "root [\"\"]");
+        assert_eq!(body(&mount, "http://localhost/api/v1"), "root [\"api\", \"v1\"]");
+
+        mount.mount("/api", Named("api"));
+        assert_eq!(body(&mount, "http://localhost/"), "root [\"\"]");
+        assert_eq!(body(&mount, "http://localhost/api/v1"), "api [\"v1\"]");
+        assert_eq!(body(&mount, "http://localhost/apis"), "root [\"apis\"]");
+    }
+
+    #[test]
+    fn it_mounts_layered_routes() {
+        for &reversed in &[false, true] {
+            let mut mount = Mount::new();
+            if reversed {
+                mount.mount("/a", Named("a"));
+                mount.mount("/a/b", Named("a/b"));
+            } else {
+                mount.mount("/a/b", Named("a/b"));
+                mount.mount("/a", Named("a"));
+            }
+
+            let body = |url| response_body(dispatch(&mount, url).unwrap());
+            assert_eq!(body("http://localhost/a/b/c"), r#"a/b ["c"]"#);
+            assert_eq!(body("http://localhost/a/x"), r#"a ["x"]"#);
+            assert_eq!(body("http://localhost/a"), r#"a []"#);
+            assert_eq!(format!("{:?}", mount),
+                       r#"Mount { routes: ["/a" (len 1), "/a/b" (len 2)] }"#);
+        }
+    }
+
+    #[test]
+    fn it_allows_valid_routes_when_strict() {
+        let mut mount = Mount::new();
+        mount.strict(true);
+        mount.mount("/", send_hello).mount("/api/", send_hello).mount("api/v1", send_hello);
+        assert_eq!(mount.len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "contains an empty segment")]
+    fn it_rejects_empty_segments_when_strict() {
+        let mut mount = Mount::new();
+        mount.strict(true);
+        mount.mount("/api//v1", send_hello);
+    }
+
+    #[test]
+    #[should_panic(expected = "is empty")]
+    fn it_rejects_an_empty_route() {
+        Mount::new().mount("", send_hello);
+    }
+
+    #[test]
+    #[should_panic(expected = "is empty")]
+    fn it_rejects_a_whitespace_route() {
+        Mount::new().mount("  ", send_hello);
+    }
+
+    #[test]
+    fn it_finds_nothing_on_an_empty_route() {
+        let mut mount = Mount::new();
+        mount.mount("/", send_hello);
+        let base = Url::parse("http://localhost/").unwrap();
+        for route in &["", "  "] {
+            assert!(mount.handler_at(route).is_none());
+            assert!(! mount.has_subtree(route));
+            assert!(mount.routes_under(route).is_empty());
+            assert!(mount.url_for(route, &base).is_none());
+            assert!(mount.set_enabled(route, false).is_err());
+            assert!(mount.remount(route, "/other").is_err());
+            assert!(mount.unmount(route).is_none());
+            assert_eq!(mount.unmount_subtree(route), 0);
+        }
+        assert!(mount.handler_at("/").is_some());
+    }
+
+    #[test]
+    fn it_mounts_owned_routes() {
+        let mut mount = Mount::new();
+        for version in 1..3 {
+            mount.mount(format!("/api/v{}", version), send_hello);
+        }
+        mount.mount_arc("/shared".to_string(), Arc::new(send_hello));
+        assert!(test_url_matches(&mount, "http://localhost/api/v1"));
+        assert!(test_url_matches(&mount, "http://localhost/api/v2/users"));
+        assert!(test_url_matches(&mount, "http://localhost/shared"));
+    }
+
+    #[test]
+    fn it_unmounts() {
+        let mut mount = Mount::new();
+        mount.mount("/api", send_hello);
+        mount.mount("/api/v1", send_hello);
+        assert!(mount.unmount("/api").is_some());
+        assert!(mount.unmount("/api").is_none());
+        assert!(mount.unmount("/missing").is_none());
+        assert!(! test_url_matches(&mount, "http://localhost/api"));
+        assert!(! test_url_matches(&mount, "http://localhost/api/other"));
+        assert!(test_url_matches(&mount, "http://localhost/api/v1"));
+        assert!(test_url_matches(&mount, "http://localhost/api/v1/users"));
+    }
+
+    #[test]
+    fn it_remounts() {
+        let mut mount = Mount::new();
+        mount.mount("/old/api", Named("api"));
+        assert_eq!(mount.remount("/old/api", "/api"), Ok(()));
+        assert_eq!(mount.remount("/old/api", "/api"),
+                   Err(MountError::NoRoute { path: "/old/api".to_string() }));
+
+        assert_eq!(mount.len(), 1);
+        assert!(! test_url_matches(&mount, "http://localhost/old/api"));
+        let res = dispatch(&mount, "http://localhost/api/users").unwrap();
+        assert_eq!(response_body(res), r#"api ["users"]"#);
+
+        mount.remount("/api", "/v2/api/").unwrap();
+        let res = dispatch(&mount, "http://localhost/v2/api/users").unwrap();
+        assert_eq!(response_body(res), r#"api ["users"]"#);
+    }
+
+    #[test]
+    fn it_lists_routes() {
+        let mut mount = Mount::new();
+        mount.mount("/", send_hello);
+        mount.mount("/api/v1", send_hello);
+        mount.mount("/api/v2/", send_hello);
+        let mut routes: Vec<String> = mount.routes().collect();
+        routes.sort();
+        assert_eq!(routes, vec!["/".to_string(), "/api/v1".to_string(), "/api/v2".to_string()]);
+        assert_eq!(mount.routes().collect::<Vec<_>>(), mount.routes().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn it_uses_the_fallback() {
+        fn fallback(req: &mut Request) -> IronResult<Response> {
+            let original = req.extensions.get::<OriginalUrl>().unwrap().path.clone();
+            Ok(Response::with((status::Ok, format!("fallback {:?} {:?}", req.url.path, original))))
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/api", send_hello);
+        assert!(! test_url_matches(&mount, "http://localhost/other/path"));
+
+        mount.fallback(fallback);
+        assert!(test_url_matches(&mount, "http://localhost/api"));
+        let res = dispatch(&mount, "http://localhost/other/path").unwrap();
+        assert_eq!(response_body(res), r#"fallback ["other", "path"] ["other", "path"]"#);
+
+        let mut outer = Mount::new();
+        outer.mount("/app", mount);
+        let res = dispatch(&outer, "http://localhost/app/other").unwrap();
+        assert_eq!(response_body(res), r#"fallback ["other"] ["app", "other"]"#);
+    }
+
+    #[test]
+    fn it_mounts_routes_with_the_mount_checked_macro() {
+        let mut mount = Mount::new();
+        let mounted = mount_checked!(mount, "/" => Named("root"), "/api/" => Named("api"),
+                                     "/web" => Named("web"),);
+        assert_eq!(mounted, Ok(()));
+        assert_eq!(mount.len(), 3);
+        let res = dispatch(&mount, "http://localhost/api/users").unwrap();
+        assert_eq!(response_body(res), r#"api ["users"]"#);
+
+        let problem = |route: &'static str| {
+            mount_checked!(Mount::new(), route => Named("x")).err().map(|err| err.problem)
+        };
+        assert_eq!(problem("/api//users"), Some("contains an empty segment".to_string()));
+        assert_eq!(problem("/api/../users"), Some(r#"contains a ".." segment"#.to_string()));
+        assert_eq!(problem("/./api"), Some(r#"contains a "." segment"#.to_string()));
+        assert!(problem("//").is_some());
+        assert!(problem(" ").is_some());
+        assert_eq!(problem("/api/users/"), None);
+
+        // Nothing is mounted unless every route is valid.
+        let mut mount = Mount::new();
+        let err = mount_checked!(mount, "/a" => Named("a"), "/b//c" => Named("b")).unwrap_err();
+        assert_eq!(err.to_string(), r#"Invalid Route: "/b//c" contains an empty segment"#);
+        assert!(mount.is_empty());
+    }
+
+    #[test]
+    fn it_tries_each_fallback_in_turn() {
+        fn static_files(req: &mut Request) -> IronResult<Response> {
+            if req.url.path.last().map_or(false, |file| file.ends_with(".css")) {
+                Ok(Response::with((status::Ok, "file")))
+            } else {
+                Err(IronError::new(MountError::NotHandled, status::NotFound))
+            }
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/api", Named("api"));
+        mount.fallback(static_files);
+        mount.fallback(|req: &mut Request| -> IronResult<Response> {
+            if req.url.path[0] == "proxied" {
+                Ok(Response::with((status::Ok, "proxy")))
+            } else {
+                Err(IronError::new(MountError::NotHandled, status::NotFound))
+            }
+        });
+
+        let result = |mount: &Mount, url| match dispatch(mount, url) {
+            Ok(res) => Ok(response_body(res)),
+            Err(err) => Err(err.error.downcast::<MountError>().cloned())
+        };
+        assert_eq!(result(&mount, "http://localhost/site.css"), Ok("file".to_string()));
+        assert_eq!(result(&mount, "http://localhost/proxied/page"), Ok("proxy".to_string()));
+        assert_eq!(result(&mount, "http://localhost/other"),
+                   Err(Some(MountError::NoRoute { path: "/other".to_string() })));
+
+        mount.fallback(Named("not found"));
+        assert_eq!(result(&mount, "http://localhost/other"),
+                   Ok(r#"not found ["other"]"#.to_string()));
+        assert_eq!(mount.describe(), "* /api\n* (fallback)\n* (fallback)\n* (fallback)\n");
+    }
+
+    #[test]
+    fn it_uses_the_most_specific_subtree_default() {
+        let mut mount = Mount::new();
+        mount.mount("/api/users", Named("users"));
+        mount.mount_method(Method::Post, "/api/form", Named("form"));
+        mount.mount_default("/api", Named("api default"));
+        mount.mount_default("/api/v2/", Named("v2 default"));
+
+        let body = |mount: &Mount, url| dispatch(mount, url).map(response_body).ok();
+        assert_eq!(body(&mount, "http://localhost/api/users/1"),
+                   Some(r#"users ["1"]"#.to_string()));
+        assert_eq!(body(&mount, "http://localhost/api/missing"),
+                   Some(r#"api default ["api", "missing"]"#.to_string()));
+        assert_eq!(body(&mount, "http://localhost/api//v2/x"),
+                   Some(r#"v2 default ["api", "", "v2", "x"]"#.to_string()));
+        assert_eq!(body(&mount, "http://localhost/api/form"), None);
+        assert_eq!(body(&mount, "http://localhost/other"), None);
+        assert!(mount.would_match("/api/missing"));
+        assert!(! mount.would_match("/other"));
+
+        let peek = |mount: &Mount, url| {
+            let data = Cursor::new("".to_string().into_bytes());
+            let mut stream = mock::MockStream::new(data);
+            let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+            let req = mock::request::new(Method::Get, Url::parse(url).unwrap(), &mut reader);
+            mount.peek_match(&req).map(|handler| handler as *const Handler)
+        };
+        let api_default = mount.defaults.get(&vec!["api".to_string()])
+            .map(|handler| &**handler as *const Handler);
+        assert!(api_default.is_some());
+        assert_eq!(peek(&mount, "http://localhost/api/missing"), api_default);
+        assert_eq!(peek(&mount, "http://localhost/other"), None);
+
+        mount.fallback(Named("fallback"));
+        assert_eq!(body(&mount, "http://localhost/other"),
+                   Some(r#"fallback ["other"]"#.to_string()));
+        assert_eq!(body(&mount, "http://localhost/api"),
+                   Some(r#"api default ["api"]"#.to_string()));
+        assert_eq!(mount.describe(), "* /api (default)\n* /api/users\n* /api/v2 (default)\n\
+                                      POST /api/form\n* (fallback)\n");
+    }
+
+    #[test]
+    fn it_only_matches_whole_segments() {
+        let mut mount = Mount::new();
+        mount.mount("/foo", Named("foo"));
+        mount.mount("/foo/bar", Named("bar"));
+
+        let body = |mount: &Mount, url| dispatch(mount, url).map(response_body).ok();
+        assert_eq!(body(&mount, "http://localhost/foo/x"), Some(r#"foo ["x"]"#.to_string()));
+        assert_eq!(body(&mount, "http://localhost/foobar"), None);
+        assert_eq!(body(&mount, "http://localhost/fo"), None);
+        assert_eq!(body(&mount, "http://localhost/foo%2Fbar"), None);
+        assert_eq!(body(&mount, "http://localhost/foo/bar%2Fbaz"),
+                   Some(r#"foo ["bar%2Fbaz"]"#.to_string()));
+
+        mount.mount_segments(&["foo/bar"], Named("encoded"));
+        assert_eq!(body(&mount, "http://localhost/foo%2Fbar"), Some(r#"encoded []"#.to_string()));
+        assert_eq!(body(&mount, "http://localhost/foo/bar"), Some(r#"bar []"#.to_string()));
+    }
+
+    #[test]
+    fn it_matches_case_insensitively() {
+        fn echo_path(req: &mut Request) -> IronResult<Response> {
+            Ok(Response::with((status::Ok, format!("{:?}", req.url.path))))
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/Exact", send_hello);
+        assert!(! test_url_matches(&mount, "http://localhost/exact"));
+
+        mount.case_insensitive(true);
+        mount.mount("/API/Users", echo_path);
+        assert!(test_url_matches(&mount, "http://localhost/api/users"));
+        let res = dispatch(&mount, "http://localhost/Api/USERS/Bob").unwrap();
+        assert_eq!(response_body(res), r#"["Bob"]"#);
+    }
+
+    #[test]
+    fn it_matches_routes_mounted_with_mount_ci_case_insensitively() {
+        let mut mount = Mount::new();
+        mount.mount_ci("/Docs", Named("docs"));
+        mount.mount("/docs", Named("literal"));
+        mount.mount("/token/AbC", Named("token"));
+        mount.mount_ci("/users/:Id", Named("user"));
+
+        let body = |url| dispatch(&mount, url).map(response_body).ok();
+        assert_eq!(body("http://localhost/DOCS/Intro"), Some(r#"docs ["Intro"]"#.to_string()));
+        assert_eq!(body("http://localhost/docs/intro"), Some(r#"literal ["intro"]"#.to_string()));
+        assert_eq!(body("http://localhost/token/AbC"), Some(r#"token []"#.to_string()));
+        assert_eq!(body("http://localhost/token/abc"), None);
+        assert_eq!(body("http://localhost/Users/Bob"), Some(r#"user []"#.to_string()));
+        assert_eq!(mount.lookup(&["USERS".to_string(), "Bob".to_string()]).unwrap().params,
+                   vec![("Id".to_string(), "Bob".to_string())].into_iter().collect());
+    }
+
+    #[test]
+    fn it_refuses_to_overwrite_with_try_mount() {
+        let mut mount = Mount::new();
+        assert!(mount.try_mount("/api", send_hello).is_ok());
+        assert!(mount.try_mount("/api/v1", send_hello).is_ok());
+        match mount.try_mount("/api/", send_hello) {
+            Err(err) => assert_eq!(err.route, "/api/"),
+            Ok(_) => panic!("Expected /api/ to already be mounted.")
+        }
+    }
+
+    #[test]
+    fn it_negotiates_media_types() {
+        let mut mount = Mount::new();
+        mount.mount_accept("/report", "text/html", Named("html"));
+        mount.mount_accept("/report", "application/json", Named("json"));
+        mount.mount_accept("/data", "application/json", Named("json"));
+
+        let accept = |mount: &Mount, url, accept: &str| {
+            let accept = accept.as_bytes().to_vec();
+            match dispatch_with(mount, url, |req| req.headers.set_raw("Accept", vec![accept])) {
+                Ok(res) => Ok(response_body(res)),
+                Err(err) => Err((err.error.downcast::<MountError>().cloned(), err.response.status))
+            }
+        };
+        assert_eq!(accept(&mount, "http://localhost/report", "application/json"),
+                   Ok(r#"json []"#.to_string()));
+        assert_eq!(accept(&mount, "http://localhost/report", "text/*;q=0.5, application/*;q=0.9"),
+                   Ok(r#"json []"#.to_string()));
+        assert_eq!(accept(&mount, "http://localhost/report", "*/*;q=0.1, text/html;q=0.2"),
+                   Ok(r#"html []"#.to_string()));
+        assert_eq!(accept(&mount, "http://localhost/report", "*/*"), Ok(r#"html []"#.to_string()));
+        assert_eq!(accept(&mount, "http://localhost/data", "text/html"),
+                   Err((Some(MountError::NotAcceptable), Some(status::NotAcceptable))));
+
+        mount.mount("/data", Named("default"));
+        assert_eq!(accept(&mount, "http://localhost/data", "text/html"),
+                   Ok(r#"default []"#.to_string()));
+        let res = dispatch(&mount, "http://localhost/report").unwrap();
+        assert_eq!(response_body(res), r#"html []"#);
+    }
+
+    #[test]
+    fn it_peeks_at_the_handler_for_a_request() {
+        let mut mount = Mount::new();
+        mount.global_prefix("/app");
+        mount.mount("/api", Named("api"));
+        mount.mount_method(Method::Post, "/form", Named("form"));
+
+        let peek = |mount: &Mount, url| {
+            let data = Cursor::new("".to_string().into_bytes());
+            let mut stream = mock::MockStream::new(data);
+            let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+            let url = Url::parse(url).unwrap();
+            let req = mock::request::new(Method::Get, url, &mut reader);
+            let handler = mount.peek_match(&req).map(|handler| handler as *const Handler);
+            assert!(! req.extensions.contains::<OriginalUrl>());
+            handler
+        };
+        let api = mount.handler_at("/api").map(|handler| handler as *const Handler);
+        assert!(api.is_some());
+        assert_eq!(peek(&mount, "http://localhost/app/api/users"), api);
+        assert_eq!(peek(&mount, "http://localhost/api/users"), None);
+        assert_eq!(peek(&mount, "http://localhost/app/form"), None);
+        assert_eq!(peek(&mount, "http://localhost/app/other"), None);
+
+        mount.fallback(Named("fallback"));
+        assert!(peek(&mount, "http://localhost/app/other").is_some());
+        assert_eq!(peek(&mount, "http://localhost/app/form"), None);
+    }
+
+    #[test]
+    fn it_calls_lifecycle_hooks_when_mounting_and_unmounting() {
+        struct Watcher(Arc<Mutex<Vec<String>>>, &'static str);
+
+        impl Handler for Watcher {
+            fn handle(&self, _: &mut Request) -> IronResult<Response> {
+                Ok(Response::with((status::Ok, self.1)))
+            }
+        }
+
+        impl MountedHandler for Watcher {
+            fn on_mount(&self) { self.0.lock().unwrap().push(format!("mount {}", self.1)); }
+            fn on_unmount(&self) { self.0.lock().unwrap().push(format!("unmount {}", self.1)); }
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut mount = Mount::new();
+        mount.mount_lifecycle("/a", Watcher(events.clone(), "a"));
+        mount.mount_lifecycle("/b", Watcher(events.clone(), "b"));
+        mount.mount("/c", Watcher(events.clone(), "c"));
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/a").unwrap()), "a");
+
+        mount.mount_lifecycle("/a", Watcher(events.clone(), "a2"));
+        assert!(mount.unmount("/b").is_some());
+        mount.unmount("/c");
+        mount.clear();
+        assert_eq!(*events.lock().unwrap(),
+                   vec!["mount a", "mount b", "unmount a", "mount a2", "unmount b",
+                        "unmount a2"]);
+
+        events.lock().unwrap().clear();
+        mount.mount_lifecycle("/d", Watcher(events.clone(), "d"));
+        let mut copy = mount.clone();
+        copy.clear();
+        assert_eq!(*events.lock().unwrap(), vec!["mount d"]);
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/d").unwrap()), "d");
+        mount.clear();
+        mount.clear();
+        assert_eq!(*events.lock().unwrap(), vec!["mount d", "unmount d"]);
+    }
+
+    #[test]
+    fn it_returns_the_depth_of_a_mounted_route() {
+        let mut mount = Mount::new();
+        assert_eq!(mount.mount_with_depth("/", Named("root")), 0);
+        assert_eq!(mount.mount_with_depth("/api/v1/", Named("api")), 2);
+        assert_eq!(mount.mount_with_depth("/a/./b/../c", Named("c")), 2);
+        let res = dispatch(&mount, "http://localhost/api/v1/users").unwrap();
+        assert_eq!(response_body(res), r#"api ["users"]"#);
+    }
+
+    #[test]
+    fn it_keeps_the_first_handler_with_mount_or_else() {
+        let mut mount = Mount::new();
+        assert!(mount.mount_or_else("/api", Named("first")));
+        assert!(! mount.mount_or_else("/api/", Named("second")));
+        assert!(mount.mount_or_else("/api/v1", Named("third")));
+
+        let res = dispatch(&mount, "http://localhost/api").unwrap();
+        assert_eq!(response_body(res), r#"first []"#);
+    }
+
+    #[test]
+    fn it_normalizes_dot_segments_in_routes() {
+        let mut mount = Mount::new();
+        mount.mount("/a/./b", send_hello);
+        mount.mount("/c/../d", send_hello);
+        mount.mount("/../e", send_hello);
+        let mut routes: Vec<String> = mount.routes().collect();
+        routes.sort();
+        assert_eq!(routes, vec!["/a/b".to_string(), "/d".to_string(), "/e".to_string()]);
+        assert!(test_url_matches(&mount, "http://localhost/a/b"));
+        assert!(test_url_matches(&mount, "http://localhost/d"));
+        assert!(test_url_matches(&mount, "http://localhost/e"));
+        assert!(! test_url_matches(&mount, "http://localhost/c"));
+    }
+
+    #[test]
+    fn it_exposes_the_mounted_path() {
+        fn echo_prefix(req: &mut Request) -> IronResult<Response> {
+            let prefix = req.extensions.get::<MountedPath>().unwrap().clone();
+            assert_eq!(current_prefix(req), Some(prefix.clone()));
+            Ok(Response::with((status::Ok, format!("{:?}", prefix))))
+        }
+
+        let unmounted = |req: &mut Request| -> IronResult<Response> {
+            assert_eq!(current_prefix(req), None);
+            Ok(Response::new())
+        };
+        assert!(dispatch(&unmounted, "http://localhost/v1").is_ok());
+
+        let mut inner = Mount::new();
+        inner.mount("/api", echo_prefix);
+        let mut mount = Mount::new();
+        mount.mount("/v1", inner);
+        mount.mount("/direct", echo_prefix);
+
+        let res = dispatch(&mount, "http://localhost/v1/api/users").unwrap();
+        assert_eq!(response_body(res), r#"["v1", "api"]"#);
+        let res = dispatch(&mount, "http://localhost/direct/").unwrap();
+        assert_eq!(response_body(res), r#"["direct"]"#);
+    }
+
+    #[test]
+    fn it_defaults_to_empty() {
+        let mount: Mount = Default::default();
+        assert!(! test_url_matches(&mount, "http://localhost/"));
+    }
+
+    #[test]
+    fn it_formats_routes_for_debugging() {
+        let mut mount = Mount::new();
+        mount.mount("/api/v1", send_hello);
+        mount.mount("/api", send_hello);
+        assert_eq!(format!("{:?}", mount),
+                   r#"Mount { routes: ["/api" (len 1), "/api/v1" (len 2)] }"#);
+    }
+
+    #[test]
+    fn it_counts_mounted_handlers() {
+        let mut mount = Mount::new();
+        assert!(mount.is_empty());
+        mount.mount("/a/b/c", send_hello);
+        assert_eq!(mount.len(), 1);
+        mount.mount("/a", send_hello).mount("/a/", send_hello);
+        assert_eq!(mount.len(), 2);
+        mount.unmount("/a/b/c");
+        assert_eq!(mount.len(), 1);
+        assert!(! mount.is_empty());
+    }
+
+    #[test]
+    fn it_clears_mounted_handlers() {
+        let mut mount = Mount::new();
+        mount.mount("/", send_hello).mount("/api", send_hello);
+        mount.clear();
+        assert_eq!(mount.len(), 0);
+        assert!(! test_url_matches(&mount, "http://localhost/api"));
+
+        mount.fallback(Named("fallback"));
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/api").unwrap()),
+                   "fallback [\"api\"]");
+    }
+
+    #[test]
+    fn it_mounts_on_hosts() {
+        let mut mount = Mount::new();
+        mount.mount_host("Example.com:80", "/", Named("example"));
+        mount.mount_host("other.com", "/app", Named("other"));
+        mount.mount("/app/admin", Named("any"));
+
+        let with_host = |host: &str, url: &str| {
+            let host = host.to_string();
+            dispatch_with(&mount, url, move |req| {
+                req.headers.set(headers::Host { hostname: host, port: Some(8080) });
+            })
+        };
+        let res = with_host("example.com", "http://localhost/app/admin").unwrap();
+        assert_eq!(response_body(res), r#"example ["app", "admin"]"#);
+        let res = with_host("other.com", "http://localhost/app/admin").unwrap();
+        assert_eq!(response_body(res), r#"other ["admin"]"#);
+        let res = with_host("unknown.com", "http://localhost/app/admin").unwrap();
+        assert_eq!(response_body(res), r#"any []"#);
+        assert!(with_host("unknown.com", "http://localhost/app").is_err());
+    }
+
+    #[test]
+    fn it_mounts_on_wildcard_hosts() {
+        let mut mount = Mount::new();
+        mount.mount_host("*.example.com", "/app", Named("subdomain"));
+        mount.mount_host("www.example.com", "/app", Named("www"));
+        mount.mount("/", Named("any"));
+
+        let with_host = |host: &str, url: &str| {
+            let host = host.to_string();
+            response_body(dispatch_with(&mount, url, move |req| {
+                req.headers.set(headers::Host { hostname: host, port: None });
+            }).unwrap())
+        };
+        assert_eq!(with_host("api.example.com", "http://localhost/app/x"), r#"subdomain ["x"]"#);
+        assert_eq!(with_host("API.Example.com", "http://localhost/app/x"), r#"subdomain ["x"]"#);
+        assert_eq!(with_host("www.example.com", "http://localhost/app/x"), r#"www ["x"]"#);
+        assert_eq!(with_host("example.com", "http://localhost/app/x"), r#"any ["app", "x"]"#);
+        assert_eq!(with_host("a.b.example.com", "http://localhost/app/x"),
+                   r#"any ["app", "x"]"#);
+        assert_eq!(with_host("api.example.com", "http://localhost/other"), r#"any ["other"]"#);
+    }
+
+    #[test]
+    fn it_mounts_for_methods() {
+        let mut mount = Mount::new();
+        mount.mount_method(Method::Get, "/items", Named("read"));
+        mount.mount_method(Method::Post, "/items", Named("write"));
+        mount.mount_method(Method::Get, "/things", Named("read"));
+        mount.mount("/things", Named("any"));
+
+        let with_method = |method: Method, url: &str| {
+            dispatch_with(&mount, url, move |req| req.method = method)
+        };
+        let res = with_method(Method::Get, "http://localhost/items/1").unwrap();
+        assert_eq!(response_body(res), r#"read ["1"]"#);
+        let res = with_method(Method::Post, "http://localhost/items").unwrap();
+        assert_eq!(response_body(res), r#"write []"#);
+        let err = with_method(Method::Delete, "http://localhost/items").err().unwrap();
+        assert_eq!(err.response.status, Some(status::MethodNotAllowed));
+        assert_eq!(err.response.headers.get::<headers::Allow>(),
+                   Some(&headers::Allow(vec![Method::Get, Method::Post])));
+
+        let res = with_method(Method::Get, "http://localhost/things").unwrap();
+        assert_eq!(response_body(res), r#"read []"#);
+        let res = with_method(Method::Delete, "http://localhost/things").unwrap();
+        assert_eq!(response_body(res), r#"any []"#);
+    }
+
+    #[test]
+    fn it_lists_the_methods_allowed_for_a_path() {
+        let mut mount = Mount::new();
+        mount.mount_method(Method::Get, "/items", Named("read"));
+        mount.mount_method(Method::Post, "/items/:id", Named("write"));
+        mount.mount_method(Method::Get, "/items/:id", Named("read"));
+        mount.mount("/things", Named("any"));
+
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(mount.allowed_methods(&path(&["items", "1"])), vec![Method::Post, Method::Get]);
+        assert_eq!(mount.allowed_methods(&path(&["items"])), vec![Method::Get]);
+        assert_eq!(mount.allowed_methods(&path(&["things", "1"])).len(), 9);
+        assert_eq!(mount.allowed_methods(&path(&["other"])), vec![]);
+    }
+
+    #[test]
+    fn it_answers_options_requests_automatically() {
+        let mut mount = Mount::new();
+        mount.mount_method(Method::Get, "/items", Named("read"));
+        mount.mount_method(Method::Post, "/items", Named("write"));
+
+        let options = |mount: &Mount, url| {
+            dispatch_with(mount, url, |req| req.method = Method::Options)
+        };
+        let err = options(&mount, "http://localhost/items").err().unwrap();
+        assert_eq!(err.response.status, Some(status::MethodNotAllowed));
+
+        mount.auto_options(true);
+        let res = options(&mount, "http://localhost/items").unwrap();
+        assert_eq!(res.status, Some(status::Ok));
+        assert_eq!(res.headers.get::<headers::Allow>(),
+                   Some(&headers::Allow(vec![Method::Get, Method::Post])));
+        assert!(options(&mount, "http://localhost/missing").is_err());
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/items").unwrap()), "read []");
+
+        // Only handlers serving the request's host are taken into account.
+        mount.mount_host("example.com", "/admin", Named("admin"));
+        let options_for = |host: &str| dispatch_with(&mount, "http://localhost/admin", |req| {
+            req.method = Method::Options;
+            req.headers.set(headers::Host { hostname: host.to_string(), port: None });
+        });
+        assert!(options_for("localhost").is_err());
+        assert_eq!(options_for("example.com").unwrap().status, Some(status::Ok));
+
+        // Requests for disabled routes are refused as usual.
+        mount.set_enabled("/items", false).unwrap();
+        let err = options(&mount, "http://localhost/items").err().unwrap();
+        assert_eq!(err.response.status, Some(status::ServiceUnavailable));
+
+        // A request which is answered automatically isn't dispatched to any handler.
+        let peeks = |mount: &Mount, method| {
+            let data = Cursor::new("".to_string().into_bytes());
+            let mut stream = mock::MockStream::new(data);
+            let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+            let url = Url::parse("http://localhost/items").unwrap();
+            mount.peek_match(&mock::request::new(method, url, &mut reader)).is_some()
+        };
+        assert!(! peeks(&mount, Method::Options));
+        mount.set_enabled("/items", true).unwrap();
+        assert!(! peeks(&mount, Method::Options));
+        assert!(peeks(&mount, Method::Get));
+    }
+
+    #[test]
+    fn it_redirects_to_add_a_trailing_slash() {
+        let mut inner = Mount::new();
+        inner.redirect_trailing_slash(true).mount("/", Named("inner"));
+        let mut mount = Mount::new();
+        mount.redirect_trailing_slash(true);
+        mount.mount("/docs", Named("docs")).mount("/nested", inner);
+
+        let res = dispatch(&mount, "http://localhost/docs?page=2").unwrap();
+        assert_eq!(res.status, Some(status::MovedPermanently));
+        assert_eq!(res.headers.get::<headers::Location>().unwrap().0,
+                   "http://localhost:80/docs/?page=2");
+        let res = dispatch(&mount, "http://localhost/docs/").unwrap();
+        assert_eq!(response_body(res), r#"docs [""]"#);
+        let res = dispatch(&mount, "http://localhost/docs/page").unwrap();
+        assert_eq!(response_body(res), r#"docs ["page"]"#);
+
+        mount.redirect_trailing_slash(false);
+        let res = dispatch(&mount, "http://localhost/docs").unwrap();
+        assert_eq!(response_body(res), r#"docs []"#);
+        let res = dispatch(&mount, "http://localhost/nested").unwrap();
+        assert_eq!(res.headers.get::<headers::Location>().unwrap().0,
+                   "http://localhost:80/nested/");
+    }
+
+    #[test]
+    fn it_decodes_path_segments_before_matching() {
+        let mut mount = Mount::new();
+        mount.mount("/api", Named("api"));
+        mount.mount("/a/b", Named("ab"));
+        mount.mount("/%FF", Named("invalid"));
+
+        let res = dispatch(&mount, "http://localhost/%61pi/users/%62").unwrap();
+        assert_eq!(response_body(res), r#"api ["users", "%62"]"#);
+        assert!(! test_url_matches(&mount, "http://localhost/a%2Fb"));
+        let res = dispatch(&mount, "http://localhost/api/a%2Fb").unwrap();
+        assert_eq!(response_body(res), r#"api ["a%2Fb"]"#);
+        let res = dispatch(&mount, "http://localhost/%FF/x").unwrap();
+        assert_eq!(response_body(res), r#"invalid ["x"]"#);
+    }
+
+    #[test]
+    fn it_shares_handlers_between_routes() {
+        struct Counter(AtomicUsize);
+
+        impl Handler for Counter {
+            fn handle(&self, _: &mut Request) -> IronResult<Response> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(Response::new())
+            }
+        }
+
+        let counter = Arc::new(Counter(AtomicUsize::new(0)));
+        let mut mount = Mount::new();
+        mount.mount_arc("/v1", counter.clone()).mount_arc("/v2", counter.clone());
+        assert!(test_url_matches(&mount, "http://localhost/v1"));
+        assert!(test_url_matches(&mount, "http://localhost/v2"));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+
+        let unmounted = mount.unmount("/v1").unwrap();
+        assert!(test_url_matches(&mount, "http://localhost/v2"));
+        drop(unmounted);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn it_mounts_a_handler_on_several_routes() {
+        let mut mount = Mount::new();
+        mount.mount_all(&["/images", "/img", "/static/assets"], Named("assets"));
+
+        assert_eq!(mount.len(), 3);
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/images/logo.png"), r#"assets ["logo.png"]"#);
+        assert_eq!(body("http://localhost/img/logo.png"), r#"assets ["logo.png"]"#);
+        assert_eq!(body("http://localhost/static/assets/logo.png"), r#"assets ["logo.png"]"#);
+    }
+
+    #[test]
+    fn it_orders_handlers_by_priority() {
+        let mut mount = Mount::new();
+        mount.mount("/users/me", Named("me"));
+        mount.mount("/users/:id", Named("user"));
+        mount.mount("/posts/latest", Named("latest"));
+        mount.mount_priority("/posts/:id", 1, Named("post"));
+        mount.mount_priority("/posts", 5, Named("posts"));
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/users/me"), r#"me []"#);
+        assert_eq!(body("http://localhost/users/7"), r#"user []"#);
+        assert_eq!(body("http://localhost/posts/latest"), r#"post []"#);
+        assert_eq!(body("http://localhost/posts/latest/comments"), r#"post ["comments"]"#);
+        assert_eq!(body("http://localhost/posts"), r#"posts []"#);
+    }
+
+    #[test]
+    fn it_captures_wildcard_segments() {
+        fn echo_params(req: &mut Request) -> IronResult<Response> {
+            let params = req.extensions.get::<Params>().unwrap();
+            let mut params: Vec<_> = params.iter().collect();
+            params.sort();
+            Ok(Response::with((status::Ok, format!("{:?} {:?}", params, req.url.path))))
+        }
+
+        let mut inner = Mount::new();
+        inner.mount("/:post", echo_params);
+        let mut mount = Mount::new();
+        mount.mount("/users/:id/profile", echo_params);
+        mount.mount("/users/new", Named("new"));
+        mount.mount("/users/:id", Named("user"));
+        mount.mount("/blogs/:blog", inner);
+
+        let res = dispatch(&mount, "http://localhost/users/42/profile/edit").unwrap();
+        assert_eq!(response_body(res), r#"[("id", "42")] ["edit"]"#);
+        let res = dispatch(&mount, "http://localhost/users/new").unwrap();
+        assert_eq!(response_body(res), r#"new []"#);
+        let res = dispatch(&mount, "http://localhost/users/new/profile").unwrap();
+        assert_eq!(response_body(res), r#"[("id", "new")] []"#);
+        let res = dispatch(&mount, "http://localhost/users/7/posts").unwrap();
+        assert_eq!(response_body(res), r#"user ["posts"]"#);
+        let res = dispatch(&mount, "http://localhost/blogs/rust/hello").unwrap();
+        assert_eq!(response_body(res), r#"[("blog", "rust"), ("post", "hello")] []"#);
+        assert!(! test_url_matches(&mount, "http://localhost/users"));
+    }
+
+    #[test]
+    fn it_mounts_without_stripping_the_route() {
+        let mut inner = Mount::new();
+        inner.mount_passthrough("/inner", Named("nested"));
+        let mut mount = Mount::new();
+        mount.mount_passthrough("/api", Named("proxy"));
+        mount.mount("/outer", inner);
+
+        let res = dispatch(&mount, "http://localhost/api/data").unwrap();
+        assert_eq!(response_body(res), r#"proxy ["api", "data"]"#);
+        let res = dispatch(&mount, "http://localhost/outer/inner/data").unwrap();
+        assert_eq!(response_body(res), r#"nested ["inner", "data"]"#);
+    }
+
+    #[test]
+    fn it_iterates_over_handlers() {
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root"));
+        mount.mount("/api", Named("api"));
+        mount.mount_method(Method::Post, "/api", Named("post api"));
+        mount.fallback(Named("fallback"));
+
+        let mut responses: Vec<String> = mount.iter().map(|(route, handler)| {
+            let res = dispatch(handler, "http://localhost/x").unwrap();
+            format!("{} {}", route, response_body(res))
+        }).collect();
+        responses.sort();
+        assert_eq!(responses, vec![r#"/ root ["x"]"#, r#"/api api ["x"]"#,
+                                   r#"/api post api ["x"]"#]);
+    }
+
+    #[test]
+    fn it_validates_routes() {
+        let mut mount = Mount::new();
+        mount.mount("/Docs", send_hello);
+        mount.mount_method(Method::Get, "/api", send_hello);
+        mount.mount_host("example.com", "/api", send_hello);
+        mount.mount_exact("/users", send_hello);
+        mount.mount_priority("/users", -1, send_hello);
+        assert_eq!(mount.validate(), Ok(()));
+
+        mount.mount_priority("/api", 1, send_hello);
+        mount.case_insensitive(true);
+        let problems = mount.validate().unwrap_err();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("/Docs: route contains uppercase letters"));
+        assert!(problems[1].starts_with("/api: handler is unreachable"));
+
+        let mut mount = Mount::new();
+        mount.mount_segments(&["api", ""], send_hello)
+            .mount_segments(&["files", ".."], send_hello)
+            .mount_segments(&["a/b"], send_hello);
+        let problems = mount.validate().unwrap_err();
+        assert_eq!(problems.len(), 2);
+        assert!(problems.iter().all(|p| p.contains("an empty, `.` or `..` segment")));
+        assert_eq!(dispatch(&mount, "http://localhost/api/").unwrap_err().response.status,
+                   Some(status::NotFound));
+
+        let mut mount = Mount::new();
+        mount.exact_paths(true).mount("/api/", send_hello);
+        assert_eq!(mount.validate(), Ok(()));
+    }
+
+    #[test]
+    fn it_exposes_the_routing_tree() {
+        let mut mount = Mount::new();
+        mount.mount("/api/v1", send_hello);
+        mount.mount("/api/v1/users", send_hello);
+        mount.mount_method(Method::Get, "/api/v1", send_hello);
+        mount.mount("/docs", send_hello);
+
+        let root = mount.tree();
+        assert!(! root.has_handler());
+        let children = root.children();
+        let segments: Vec<&str> = children.iter().map(|&(segment, _)| segment).collect();
+        assert_eq!(segments, vec!["api", "docs"]);
+
+        let api = children[0].1;
+        assert!(! api.has_handler());
+        let v1 = api.children()[0].1;
+        assert_eq!(v1.handler_count(), 2);
+        assert_eq!(v1.children().len(), 1);
+        assert!(v1.children()[0].1.has_handler());
+    }
+
+    #[test]
+    fn it_builds_from_routes() {
+        let routes = vec![
+            ("/a".to_string(), Box::new(Named("first a")) as Box<Handler>),
+            ("/b".to_string(), Box::new(Named("b")) as Box<Handler>),
+            ("/a/".to_string(), Box::new(Named("second a")) as Box<Handler>)
+        ];
+        let mount = Mount::from_routes(routes);
+
+        assert_eq!(mount.len(), 2);
+        let res = dispatch(&mount, "http://localhost/a/x").unwrap();
+        assert_eq!(response_body(res), r#"second a ["x"]"#);
+        let res = dispatch(&mount, "http://localhost/b").unwrap();
+        assert_eq!(response_body(res), r#"b []"#);
+    }
+
+    #[test]
+    fn it_checks_whether_paths_would_match() {
+        let mut mount = Mount::new();
+        mount.mount("/api", send_hello);
+        mount.mount_exact("/docs", send_hello);
+
+        assert!(mount.would_match("/api"));
+        assert!(mount.would_match("/api//v1/"));
+        assert!(mount.would_match("/docs/"));
+        assert!(! mount.would_match("/docs/intro"));
+        assert!(! mount.would_match("/"));
+
+        mount.fallback(send_hello);
+        assert!(mount.would_match("/"));
+    }
+
+    #[test]
+    fn it_builds_urls_for_routes() {
+        let mut mount = Mount::new();
+        mount.mount("/", send_hello);
+        mount.mount("/api/my docs", send_hello);
+
+        let base = Url::parse("https://example.com/service/?page=2").unwrap();
+        let url = |route| mount.url_for(route, &base).map(|url| url.to_string());
+        assert_eq!(url("/api/my docs/"),
+                   Some("https://example.com:443/service/api/my%20docs".to_string()));
+        assert_eq!(url("/"), Some("https://example.com:443/service".to_string()));
+        assert_eq!(url("/api"), None);
+
+        let root = Url::parse("http://localhost/").unwrap();
+        assert_eq!(mount.url_for("/", &root).unwrap().to_string(), "http://localhost:80/");
+    }
+
+    #[test]
+    fn it_merges_mounts() {
+        let mut mount = Mount::new();
+        mount.mount("/a", Named("first a"));
+        let mut other = Mount::new();
+        other.mount("/a", Named("second a"));
+        other.mount("/b/c", Named("c"));
+        other.mount_method(Method::Get, "/b/c", Named("get c"));
+        mount.extend(other);
+        Extend::extend(&mut mount, vec![("/d".to_string(), Box::new(Named("d")) as Box<Handler>)]);
+
+        assert_eq!(mount.len(), 4);
+        let res = dispatch(&mount, "http://localhost/a/x").unwrap();
+        assert_eq!(response_body(res), r#"second a ["x"]"#);
+        let res = dispatch(&mount, "http://localhost/b/c/x").unwrap();
+        assert_eq!(response_body(res), r#"get c ["x"]"#);
+        let res = dispatch(&mount, "http://localhost/d/x").unwrap();
+        assert_eq!(response_body(res), r#"d ["x"]"#);
+    }
+
+    #[test]
+    fn it_customizes_the_no_match_response() {
+        let mut mount = Mount::new();
+        let err = dispatch(&mount, "http://localhost/").err().unwrap();
+        assert_eq!(err.response.status, Some(status::NotFound));
+
+        mount.no_match_response(status::BadGateway);
+        let err = dispatch(&mount, "http://localhost/").err().unwrap();
+        assert_eq!(err.response.status, Some(status::BadGateway));
+
+        mount.no_match_with(|| Response::with((status::NotFound, "{\"error\":\"no route\"}")));
+        let err = dispatch(&mount, "http://localhost/").err().unwrap();
+        assert_eq!(err.response.status, Some(status::NotFound));
+        assert_eq!(response_body(err.response), r#"{"error":"no route"}"#);
+    }
+
+    #[test]
+    fn it_returns_the_handler_at_a_route() {
+        let mut mount = Mount::new();
+        mount.mount("/admin", Named("admin"));
+        mount.mount_method(Method::Get, "/reports", Named("reports"));
+
+        let res = dispatch(mount.handler_at("/admin/").unwrap(), "http://localhost/x").unwrap();
+        assert_eq!(response_body(res), r#"admin ["x"]"#);
+        assert!(mount.handler_at("/admin/users").is_none());
+        assert!(mount.handler_at("/reports").is_none());
+    }
+
+    #[test]
+    fn it_propagates_url_changes() {
+        let mut inner = Mount::new();
+        inner.mount("/users", |req: &mut Request| -> IronResult<Response> {
+            req.url.query = Some("page=2".to_string());
+            req.url.fragment = Some("top".to_string());
+            Ok(Response::with((status::Ok, "")))
+        });
+
+        let mut mount = Mount::new();
+        mount.mount("/api", inner);
+
+        let outer = move |req: &mut Request| -> IronResult<Response> {
+            let res = mount.handle(req);
+            assert_eq!(req.url.path, vec!["api", "users", "1"]);
+            assert_eq!(req.url.query, Some("page=2".to_string()));
+            assert_eq!(req.url.fragment, Some("top".to_string()));
+            res
+        };
+
+        assert!(dispatch(&outer, "http://localhost/api/users/1").is_ok());
+    }
+
+    #[test]
+    fn it_mounts_closures() {
+        let greeting = "pong".to_string();
+        let mut mount = Mount::new();
+        mount.mount_fn("/ping", move |_| Ok(Response::with((status::Ok, &*greeting))));
+
+        let res = dispatch(&mount, "http://localhost/ping").unwrap();
+        assert_eq!(response_body(res), "pong");
+    }
+
+    #[test]
+    fn it_mounts_exact_routes() {
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root"));
+        mount.mount_exact("/api", Named("exact"));
+        mount.mount_exact("/docs", Named("docs"));
+        mount.mount("/docs", Named("prefix"));
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/api"), "exact []");
+        assert_eq!(body("http://localhost/api/"), "exact [\"\"]");
+        assert_eq!(body("http://localhost/api/unknown"), "root [\"api\", \"unknown\"]");
+        assert_eq!(body("http://localhost/docs"), "docs []");
+        assert_eq!(body("http://localhost/docs/intro"), "prefix [\"intro\"]");
+    }
+
+    #[test]
+    fn it_reports_overwrites() {
+        use std::sync::Mutex;
+
+        let overwritten = Arc::new(Mutex::new(Vec::new()));
+        let log = overwritten.clone();
+
+        let mut mount = Mount::new();
+        mount.on_overwrite(move |route| log.lock().unwrap().push(route.to_string()));
+        mount.mount("/api", send_hello);
+        mount.mount("/api/v1", send_hello);
+        mount.mount_method(Method::Get, "/api", send_hello);
+        assert!(overwritten.lock().unwrap().is_empty());
+
+        mount.mount("/api/", send_hello);
+        mount.mount_method(Method::Get, "/api", send_hello);
+        assert_eq!(*overwritten.lock().unwrap(), vec!["/api", "/api"]);
+    }
+
+    #[test]
+    fn it_mounts_guarded_handlers() {
+        fn is_beta(req: &Request) -> bool {
+            req.headers.get_raw("X-Beta").is_some()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/", Named("stable"));
+        mount.mount_if("/beta", is_beta, Named("beta"));
+
+        let url = "http://localhost/beta/page";
+        assert_eq!(response_body(dispatch(&mount, url).unwrap()),
+                   "stable [\"beta\", \"page\"]");
+
+        let res = dispatch_with(&mount, url, |req| {
+            req.headers.set_raw("X-Beta", vec![b"1".to_vec()]);
+        });
+        assert_eq!(response_body(res.unwrap()), "beta [\"page\"]");
+    }
+
+    #[test]
+    fn it_keeps_the_original_url_in_nested_mounts() {
+        fn original_url(req: &mut Request) -> IronResult<Response> {
+            let original = req.extensions.get::<OriginalUrl>().unwrap().to_string();
+            Ok(Response::with((status::Ok, format!("{} {:?}", original, req.url.path))))
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/d", original_url);
+        for route in &["/c", "/b", "/a"] {
+            let mut outer = Mount::new();
+            outer.mount(route, mount);
+            mount = outer;
+        }
+
+        let outer = move |req: &mut Request| -> IronResult<Response> {
+            let res = mount.handle(req);
+            assert_eq!(req.url.path, vec!["a", "b", "c", "d", "e"]);
+            assert!(! req.extensions.contains::<OriginalUrl>());
+            res
+        };
+
+        let res = dispatch(&outer, "http://localhost/a/b/c/d/e?x=1").unwrap();
+        assert_eq!(response_body(res), r#"http://localhost:80/a/b/c/d/e?x=1 ["e"]"#);
+    }
+
+    #[test]
+    fn it_survives_removed_extensions() {
+        let mut inner = Mount::new();
+        inner.mount("/users", |req: &mut Request| -> IronResult<Response> {
+            req.extensions.remove::<OriginalUrl>();
+            req.extensions.remove::<MountedPath>();
+            Ok(Response::with((status::Ok, "")))
+        });
+
+        let mut mount = Mount::new();
+        mount.mount("/api", inner);
+
+        let outer = move |req: &mut Request| -> IronResult<Response> {
+            let res = mount.handle(req);
+            assert_eq!(req.url.path, vec!["api", "users", "1"]);
+            assert!(! req.extensions.contains::<OriginalUrl>());
+            res
+        };
+
+        assert!(dispatch(&outer, "http://localhost/api/users/1").is_ok());
+    }
+
+    #[test]
+    fn it_counts_requests() {
+        let mut mount = Mount::new();
+        mount.count_requests(true);
+        mount.mount("/", send_hello);
+        mount.mount("/api", send_hello);
+        mount.mount_method(Method::Post, "/api", send_hello);
+        mount.mount_if("/beta", |_: &Request| false, send_hello);
+
+        for url in &["http://localhost/api", "http://localhost/api/v1", "http://localhost/beta"] {
+            assert!(dispatch(&mount, url).is_ok());
+        }
+
+        let stats = mount.stats();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats["/"], 1);
+        assert_eq!(stats["/api"], 2);
+        assert_eq!(stats["/beta"], 0);
+    }
+
+    #[test]
+    fn it_forwards_the_prefix() {
+        fn forwarded_prefix(req: &mut Request) -> IronResult<Response> {
+            let value = req.headers.get_raw("X-Forwarded-Prefix")
+                .map(|values| String::from_utf8(values[0].clone()).unwrap());
+            Ok(Response::with((status::Ok, format!("{:?}", value))))
+        }
+
+        let mut inner = Mount::new();
+        inner.forward_prefix(true).mount("/v1", forwarded_prefix);
+        let mut mount = Mount::new();
+        mount.forward_prefix(true);
+        mount.mount("/service-a", forwarded_prefix).mount("/service-b", inner)
+            .mount("/", forwarded_prefix);
+
+        let outer = move |req: &mut Request| -> IronResult<Response> {
+            let res = mount.handle(req);
+            assert!(req.headers.get_raw("X-Forwarded-Prefix").is_none());
+            res
+        };
+
+        let res = dispatch(&outer, "http://localhost/service-a/users").unwrap();
+        assert_eq!(response_body(res), r#"Some("/service-a")"#);
+        let res = dispatch(&outer, "http://localhost/service-b/v1/users").unwrap();
+        assert_eq!(response_body(res), r#"Some("/service-b/v1")"#);
+        let res = dispatch(&outer, "http://localhost/users").unwrap();
+        assert_eq!(response_body(res), "None");
+    }
+
+    #[test]
+    fn it_reports_why_requests_were_not_matched() {
+        let mut mount = Mount::new();
+        mount.mount_method(Method::Post, "/items", send_hello);
+        mount.mount_if("/beta", |_: &Request| false, send_hello);
+
+        let error = |url| {
+            let err = dispatch(&mount, url).unwrap_err();
+            (err.error.downcast::<MountError>().cloned(), err.response.status)
+        };
+        assert_eq!(error("http://localhost/other/page"),
+                   (Some(MountError::NoRoute { path: "/other/page".to_string() }),
+                    Some(status::NotFound)));
+        assert_eq!(error("http://localhost/items"),
+                   (Some(MountError::MethodNotAllowed), Some(status::MethodNotAllowed)));
+        assert_eq!(error("http://localhost/beta"),
+                   (Some(MountError::Declined), Some(status::NotFound)));
+
+        let err = dispatch(&mount, "http://localhost/foo/bar").unwrap_err();
+        assert_eq!(err.error.to_string(), "No match for /foo/bar");
+        assert_eq!(err.error.description(), "No Match");
+
+        let mut inner = Mount::new();
+        inner.mount("/users", send_hello);
+        let mut outer = Mount::new();
+        outer.mount("/api", inner);
+        let err = dispatch(&outer, "http://localhost/api/v1/missing").unwrap_err();
+        assert_eq!(err.error.to_string(), "No match for /api/v1/missing");
+
+        let mut inner = Mount::new();
+        inner.mount("/v2/users", send_hello);
+        let mut outer = Mount::new();
+        outer.mount_rewrite("/legacy", "/v2", inner);
+        let err = dispatch(&outer, "http://localhost/legacy/other").unwrap_err();
+        assert_eq!(err.error.to_string(), "No match for /legacy/other");
+    }
+
+    #[test]
+    fn it_is_shared_between_threads() {
+        use std::thread;
+
+        let mut mount = Mount::new();
+        mount.mount("/api", Named("api"));
+        let mount = Arc::new(mount);
+
+        let threads: Vec<_> = (0..4).map(|_| {
+            let mount = mount.clone();
+            thread::spawn(move || mount.would_match("/api/v1"))
+        }).collect();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn it_mounts_for_query_pairs() {
+        let mut mount = Mount::new();
+        mount.mount("/handler", Named("default"));
+        mount.mount_query("/handler", "mode", "legacy", Named("legacy"));
+        mount.mount_query("/handler", "mode", "new", Named("new"));
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/handler?mode=legacy"), r#"legacy []"#);
+        assert_eq!(body("http://localhost/handler/x?page=2&mode=new"), r#"new ["x"]"#);
+        assert_eq!(body("http://localhost/handler?mode=other"), r#"default []"#);
+        assert_eq!(body("http://localhost/handler"), r#"default []"#);
+    }
+
+    #[test]
+    fn it_resolves_paths() {
+        fn path(segments: &[&str]) -> Vec<String> {
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/api", send_hello);
+        mount.mount("/users/:id", send_hello);
+        mount.mount_passthrough("/proxy", send_hello);
+
+        assert_eq!(mount.resolve(&path(&["api", "v1", ""])), Some((path(&["api"]), 1)));
+        assert_eq!(mount.resolve(&path(&["users", "7", ""])), Some((path(&["users", ":id"]), 2)));
+        assert_eq!(mount.resolve(&path(&["proxy", "x"])), Some((path(&["proxy"]), 0)));
+        assert_eq!(mount.resolve(&path(&["other"])), None);
+    }
+
+    #[test]
+    fn it_strips_an_exact_route_to_an_empty_path() {
+        let mut mount = Mount::new();
+        mount.mount("/blog", Named("blog"));
+
+        let body = |mount: &Mount, url| response_body(dispatch(mount, url).unwrap());
+        assert_eq!(body(&mount, "http://localhost/blog"), r#"blog []"#);
+        assert_eq!(body(&mount, "http://localhost/blog/"), r#"blog [""]"#);
+
+        mount.root_empty_path(true);
+        assert_eq!(body(&mount, "http://localhost/blog"), r#"blog [""]"#);
+        assert_eq!(body(&mount, "http://localhost/blog/"), r#"blog [""]"#);
+        assert_eq!(body(&mount, "http://localhost/blog/post"), r#"blog ["post"]"#);
+    }
+
+    #[test]
+    fn it_restores_the_path_after_rooting_an_empty_path() {
+        let mut mount = Mount::new();
+        mount.root_empty_path(true).mount("/blog", Named("blog"));
+
+        let outer = move |req: &mut Request| {
+            let body = response_body(try!(mount.handle(req)));
+            Ok(Response::with((status::Ok, format!("{} then {:?}", body, req.url.path))))
+        };
+        let body = response_body(dispatch(&outer, "http://localhost/blog").unwrap());
+        assert_eq!(body, r#"blog [""] then ["blog"]"#);
+    }
+
+    #[test]
+    fn it_flattens_mounts_with_the_same_routing_as_nesting() {
+        fn report(req: &mut Request) -> IronResult<Response> {
+            let prefix = current_prefix(req).unwrap_or(vec![]);
+            Ok(Response::with((status::Ok, format!("{:?} {:?}", prefix, req.url.path))))
+        }
+
+        fn api(with_root: bool) -> Mount {
+            let mut api = Mount::new();
+            if with_root { api.mount("/", Named("root")); }
+            api.mount("/users/:id", Named("user"))
+                .mount("/where", report)
+                .mount_passthrough("/proxy", Named("proxy"))
+                .mount_method(Method::Post, "/admin", Named("admin"));
+            api
+        }
+
+        let body = |mount: &Mount, url| match dispatch(mount, url) {
+            Ok(res) => response_body(res),
+            Err(err) => format!("{:?}", err.response.status)
+        };
+        let urls = ["http://localhost/api", "http://localhost/api/users/7/posts",
+                    "http://localhost/api/where/x", "http://localhost/api/proxy/x",
+                    "http://localhost/api/admin", "http://localhost/api/other",
+                    "http://localhost/other"];
+
+        for &with_root in &[true, false] {
+            let mut nested = Mount::new();
+            nested.mount("/", Named("outer")).mount("/api", api(with_root));
+            let mut flat = Mount::new();
+            flat.mount("/", Named("outer")).mount_flatten("/api", api(with_root));
+
+            assert!(flat.handler_at("/api/users/:id").is_some());
+            for url in &urls {
+                assert_eq!(body(&flat, url), body(&nested, url));
+            }
+        }
+    }
+
+    #[test]
+    fn it_flattens_a_mount_with_only_plain_handlers_entirely() {
+        let mut api = Mount::new();
+        api.mount("/", Named("root")).mount("/users", Named("users"));
+        let mut mount = Mount::new();
+        mount.mount_flatten("/api", api);
+
+        assert_eq!(mount.len(), 2);
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/api/users/7"), r#"users ["7"]"#);
+        assert_eq!(body("http://localhost/api/other"), r#"root ["other"]"#);
+    }
+
+    #[test]
+    fn it_shares_one_handler_instance_between_routes() {
+        let handler: Arc<Handler> = Arc::new(Named("shared"));
+        let mut mount = Mount::new();
+        mount.mount_shared(&["/images", "/img", "/static/images"], handler.clone());
+        assert_eq!(Arc::strong_count(&handler), 4);
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/img/logo.png"), r#"shared ["logo.png"]"#);
+        assert_eq!(body("http://localhost/static/images/a.png"), r#"shared ["a.png"]"#);
+
+        mount.unmount("/img");
+        assert_eq!(Arc::strong_count(&handler), 3);
+    }
+
+    #[test]
+    fn it_returns_the_replaced_handler() {
+        let mut mount = Mount::new();
+        assert!(mount.replace("/api", Named("first")).is_none());
+
+        let old = mount.replace("/api/", Named("second")).unwrap();
+        assert_eq!(response_body(dispatch(&*old, "http://localhost/").unwrap()), r#"first [""]"#);
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/api").unwrap()), "second []");
+        assert_eq!(mount.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-normalization")]
+    fn it_matches_canonically_equivalent_segments() {
+        let mut mount = Mount::new();
+        mount.mount("/caf\u{e9}", |req: &mut Request| {
+            let original = req.extensions.get::<OriginalUrl>().unwrap().path.clone();
+            Ok(Response::with((status::Ok, format!("{:?} {:?}", original, req.url.path))))
+        });
+        mount.mount("/nai\u{308}ve", Named("naive"));
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/cafe%CC%81/menu"), r#"["cafe%CC%81", "menu"] ["menu"]"#);
+        assert_eq!(body("http://localhost/caf%C3%A9/menu"), r#"["caf%C3%A9", "menu"] ["menu"]"#);
+        assert_eq!(body("http://localhost/na%C3%AFve"), "naive []");
+    }
+
+    #[test]
+    fn it_builds_a_mount_by_value() {
+        let mount = Mount::with("/api", Named("api")).route("/", Named("root"));
+        assert_eq!(mount.len(), 2);
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/api/users"), r#"api ["users"]"#);
+        assert_eq!(body("http://localhost/about"), r#"root ["about"]"#);
+    }
+
+    #[test]
+    fn it_prefers_a_deeper_mount_to_a_root_catch_all() {
+        fn path(segments: &[&str]) -> Vec<String> {
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/", Named("catch_all")).mount("/api/v1", Named("api"));
+
+        assert_eq!(mount.resolve(&path(&["api", "v1", "users"])), Some((path(&["api", "v1"]), 2)));
+        assert_eq!(mount.resolve(&path(&["api", "v2", "users"])), Some((path(&[]), 0)));
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/"), r#"catch_all [""]"#);
+        assert_eq!(body("http://localhost/api"), r#"catch_all ["api"]"#);
+        assert_eq!(body("http://localhost/api/v1"), "api []");
+        assert_eq!(body("http://localhost/api/v1/users"), r#"api ["users"]"#);
+        assert_eq!(body("http://localhost/api/v1/users/7/posts"), r#"api ["users", "7", "posts"]"#);
+        assert_eq!(body("http://localhost/api/v2/users"), r#"catch_all ["api", "v2", "users"]"#);
+    }
+
+    #[test]
+    fn it_rewrites_the_stripped_prefix() {
+        let mut mount = Mount::new();
+        mount.mount_rewrite("/legacy", "/v2", |req: &mut Request| {
+            let prefix = current_prefix(req).unwrap();
+            Ok(Response::with((status::Ok, format!("{:?} {:?}", prefix, req.url.path))))
+        });
+        mount.mount_rewrite("/old/api", "/", Named("root"));
+
+        let outer = move |req: &mut Request| {
+            let body = response_body(try!(mount.handle(req)));
+            Ok(Response::with((status::Ok, format!("{} then {:?}", body, req.url.path))))
+        };
+        let body = |url| response_body(dispatch(&outer, url).unwrap());
+        assert_eq!(body("http://localhost/legacy/users"),
+                   r#"["legacy"] ["v2", "users"] then ["legacy", "users"]"#);
+        assert_eq!(body("http://localhost/legacy"), r#"["legacy"] ["v2"] then ["legacy"]"#);
+        assert_eq!(body("http://localhost/old/api/x"), r#"root ["x"] then ["old", "api", "x"]"#);
+    }
+
+    #[test]
+    fn it_unmounts_subtrees() {
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root"))
+            .mount("/admin", Named("admin"))
+            .mount("/admin/users", Named("users"))
+            .mount_method(Method::Post, "/admin/users/:id", Named("update"))
+            .mount("/administrators", Named("other"));
+
+        assert!(mount.has_subtree("/admin"));
+        assert!(mount.has_subtree("/admin/users/:id"));
+        assert!(!mount.has_subtree("/admin/settings"));
+        assert_eq!(mount.routes_under("/admin"),
+                   vec!["/admin".to_string(), "/admin/users".to_string(),
+                        "/admin/users/:id".to_string()]);
+        assert_eq!(mount.routes_under("/").len(), 5);
+        assert!(mount.routes_under("/admin/settings").is_empty());
+
+        assert_eq!(mount.unmount_subtree("/admin"), 3);
+        assert!(!mount.has_subtree("/admin"));
+        assert_eq!(mount.unmount_subtree("/admin"), 0);
+        assert_eq!(mount.len(), 2);
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/admin/users"), r#"root ["admin", "users"]"#);
+        assert_eq!(body("http://localhost/administrators"), "other []");
+
+        // Defaults beneath the prefix go with it.
+        let mut mount = Mount::new();
+        mount.mount("/api/users", Named("users"))
+            .mount_default("/api", Named("api default"))
+            .mount_default("/api/v2", Named("v2 default"))
+            .mount_default("/web", Named("web default"));
+        assert_eq!(mount.unmount_subtree("/api"), 1);
+        assert!(dispatch(&mount, "http://localhost/api/missing").is_err());
+        assert!(dispatch(&mount, "http://localhost/api/v2/missing").is_err());
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/web/x").unwrap()),
+                   r#"web default ["web", "x"]"#);
+        mount.clear();
+        assert!(dispatch(&mount, "http://localhost/web/x").is_err());
+        mount.mount_default("/", Named("root default"));
+        assert_eq!(mount.unmount_subtree("/"), 0);
+        assert!(dispatch(&mount, "http://localhost/x").is_err());
+    }
+
+    #[test]
+    fn it_reports_each_dispatch() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+
+        let mut mount = Mount::new();
+        mount.mount("/users/:id", send_hello).fallback(send_hello);
+        mount.on_dispatch(move |route, req| {
+            log.lock().unwrap().push(format!("{:?} {:?}", route, req.url.path));
+        });
+
+        dispatch(&mount, "http://localhost/users/7/posts").unwrap();
+        dispatch(&mount, "http://localhost/other").unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![r#"["users", ":id"] ["posts"]"#.to_string()]);
+    }
+
+    #[test]
+    fn it_describes_the_routing_table() {
+        let mut mount = Mount::new();
+        mount.mount("/api/v1", send_hello)
+            .mount_method(Method::Get, "/users/:id", send_hello)
+            .mount_priority("/users/:id", 1, send_hello)
+            .mount_host("Example.com", "/app", send_hello)
+            .mount_exact("/", send_hello)
+            .mount_passthrough("/proxy", send_hello)
+            .mount_query("/search", "mode", "fast", send_hello)
+            .fallback(send_hello);
+
+        assert_eq!(mount.describe(), "* / (exact)\n\
+                                      * /api/v1\n\
+                                      * /proxy (passthrough)\n\
+                                      * /search (query mode=fast)\n\
+                                      * /users/:id (priority 1)\n\
+                                      * example.com/app\n\
+                                      GET /users/:id\n\
+                                      * (fallback)\n");
+        assert_eq!(Mount::new().describe(), "");
+    }
+
+    #[test]
+    fn it_looks_up_paths() {
+        fn path(segments: &[&str]) -> Vec<String> {
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/users/:id", send_hello).mount_passthrough("/proxy", send_hello);
+
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), "7".to_string());
+        assert_eq!(mount.lookup(&path(&["users", "7", "posts"])), Some(MatchInfo {
+            route: path(&["users", ":id"]),
+            length: 2,
+            params: params
+        }));
+        assert_eq!(mount.lookup(&path(&["proxy", "x"])), Some(MatchInfo {
+            route: path(&["proxy"]),
+            length: 0,
+            params: HashMap::new()
+        }));
+        assert_eq!(mount.lookup(&path(&["other"])), None);
+    }
+
+    #[test]
+    fn it_resets_the_original_url_per_level() {
+        fn original(req: &mut Request) -> IronResult<Response> {
+            let path = req.extensions.get::<OriginalUrl>().unwrap().path.clone();
+            Ok(Response::with((status::Ok, format!("{:?}", path))))
+        }
+
+        let mut inner = Mount::new();
+        inner.mount("/v1", original);
+        let mut mount = Mount::new();
+        mount.mount("/api", inner);
+        let res = dispatch(&mount, "http://localhost/api/v1/users").unwrap();
+        assert_eq!(response_body(res), r#"["api", "v1", "users"]"#);
+
+        let mut inner = Mount::new();
+        inner.reset_original_url_per_level(true).mount("/v1", original);
+        let mut mount = Mount::new();
+        mount.mount("/api", inner);
+        let outer = move |req: &mut Request| {
+            let body = response_body(try!(mount.handle(req)));
+            Ok(Response::with((status::Ok, format!("{} {}", body,
+                                                    req.extensions.contains::<OriginalUrl>()))))
+        };
+        let res = dispatch(&outer, "http://localhost/api/v1/users").unwrap();
+        assert_eq!(response_body(res), r#"["v1", "users"] false"#);
+    }
+
+    #[test]
+    fn it_strips_prefixes_as_handle_does() {
+        fn path(segments: &[&str]) -> Vec<String> {
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/api/users", Named("users"));
+
+        let full = path(&["api", "", "users", "7"]);
+        let route = mount.lookup(&full).unwrap().route;
+        assert_eq!(mount.strip_prefix(&full, &route), &path(&["7"])[..]);
+        assert_eq!(mount.strip_prefix(&path(&["api", "users"]), &route), &path(&[])[..]);
+        assert_eq!(mount.strip_prefix(&path(&["api", "users", ""]), &route), &path(&[""])[..]);
+        assert_eq!(mount.strip_prefix(&full, &[]), &full[..]);
+
+        let res = dispatch(&mount, "http://localhost/api//users/7").unwrap();
+        assert_eq!(response_body(res), r#"users ["7"]"#);
+    }
+
+    #[test]
+    fn it_ignores_trailing_slashes_in_routes() {
+        let mut mount = Mount::new();
+        mount.mount("/docs/", Named("slash"));
+        assert!(mount.handler_at("/docs").is_some());
+
+        mount.mount("/docs", Named("plain"));
+        assert_eq!(mount.len(), 1);
+        assert_eq!(mount.routes().collect::<Vec<_>>(), vec!["/docs".to_string()]);
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/docs/"), r#"plain [""]"#);
+        assert_eq!(body("http://localhost/docs/intro"), r#"plain ["intro"]"#);
+    }
+
+    #[test]
+    fn it_refuses_requests_for_disabled_handlers() {
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root")).mount("/beta", Named("beta"));
+        mount.mount_method(Method::Get, "/beta", Named("get"));
+
+        let status = |mount: &Mount, url| match dispatch(mount, url) {
+            Ok(res) => (None, res.status),
+            Err(err) => (err.error.downcast::<MountError>().cloned(), err.response.status)
+        };
+        assert_eq!(mount.set_enabled("/beta", false), Ok(()));
+        assert_eq!(status(&mount, "http://localhost/beta/x"),
+                   (Some(MountError::Disabled), Some(status::ServiceUnavailable)));
+        assert_eq!(status(&mount, "http://localhost/other"), (None, Some(status::Ok)));
+
+        mount.disabled_response(status::NotFound);
+        assert_eq!(status(&mount, "http://localhost/beta"),
+                   (Some(MountError::Disabled), Some(status::NotFound)));
+
+        assert_eq!(mount.set_enabled("/beta", true), Ok(()));
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/beta").unwrap()), "get []");
+        assert_eq!(mount.set_enabled("/missing", false),
+                   Err(MountError::NoRoute { path: "/missing".to_string() }));
+    }
+
+    #[test]
+    fn it_restores_the_request_when_a_handler_panics() {
+        let mut mount = Mount::new();
+        mount.mount("/api/:version", |_: &mut Request| -> IronResult<Response> {
+            panic!("handler failed")
+        });
+
+        let outer = move |req: &mut Request| {
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| mount.handle(req)));
+            let leaked = req.extensions.contains::<OriginalUrl>() ||
+                req.extensions.contains::<MountedPath>() || req.extensions.contains::<Params>();
+            Ok(Response::with((status::Ok, format!("{} {:?} {}", result.is_err(),
+                                                    req.url.path, leaked))))
+        };
+        let res = dispatch(&outer, "http://localhost/api/v1/users").unwrap();
+        assert_eq!(response_body(res), r#"true ["api", "v1", "users"] false"#);
+    }
+
+    #[test]
+    fn it_leaves_no_extensions_behind_when_refusing_requests() {
+        let mut mount = Mount::new();
+        mount.global_prefix("/app").max_depth(4).redirect_trailing_slash(true);
+        mount.mount("/dir", Named("dir"));
+        mount.mount("/off", Named("off"));
+        mount.mount_method(Method::Post, "/form", Named("form"));
+        mount.mount_accept("/report", "text/html", Named("report"));
+        mount.set_enabled("/off", false).unwrap();
+
+        let outer = move |req: &mut Request| {
+            let status = match mount.handle(req) {
+                Ok(res) => res.status,
+                Err(err) => err.response.status
+            };
+            let leaked = req.extensions.contains::<OriginalUrl>() ||
+                req.extensions.contains::<MountedPath>() || req.extensions.contains::<Params>();
+            Ok(Response::with((status::Ok, format!("{} {:?} {}", status.unwrap(),
+                                                    req.url.path, leaked))))
+        };
+        let check = |url, accept: &str| {
+            let accept = accept.as_bytes().to_vec();
+            let res = dispatch_with(&outer, url, |req| req.headers.set_raw("Accept", vec![accept]));
+            response_body(res.unwrap())
+        };
+        assert_eq!(check("http://localhost/app/a/b/c/d", "*/*"),
+                   r#"414 URI Too Long ["app", "a", "b", "c", "d"] false"#);
+        assert_eq!(check("http://localhost/other", "*/*"), r#"404 Not Found ["other"] false"#);
+        assert_eq!(check("http://localhost/app/none", "*/*"),
+                   r#"404 Not Found ["app", "none"] false"#);
+        assert_eq!(check("http://localhost/app/dir", "*/*"),
+                   r#"301 Moved Permanently ["app", "dir"] false"#);
+        assert_eq!(check("http://localhost/app/off/", "*/*"),
+                   r#"503 Service Unavailable ["app", "off", ""] false"#);
+        assert_eq!(check("http://localhost/app/form/", "*/*"),
+                   r#"405 Method Not Allowed ["app", "form", ""] false"#);
+        assert_eq!(check("http://localhost/app/report/", "application/json"),
+                   r#"406 Not Acceptable ["app", "report", ""] false"#);
+    }
+
+    #[test]
+    fn it_mounts_static_responses() {
+        let mut mount = Mount::new();
+        {
+            let body = String::from("ok");
+            mount.mount_static("/health", status::Ok, &body);
+        }
+        mount.mount_static("/teapot", status::ImATeapot, "short and stout");
+
+        let res = dispatch(&mount, "http://localhost/health/deep").unwrap();
+        assert_eq!(res.status, Some(status::Ok));
+        assert_eq!(response_body(res), "ok");
+        let res = dispatch(&mount, "http://localhost/teapot").unwrap();
+        assert_eq!(res.status, Some(status::ImATeapot));
+        assert_eq!(response_body(res), "short and stout");
+    }
+
+    #[test]
+    fn it_distinguishes_trailing_slashes_with_exact_paths() {
+        let mut mount = Mount::new();
+        mount.exact_paths(true).redirect_trailing_slash(true);
+        mount.mount("/foo", Named("foo"))
+            .mount("/foo/", Named("foo/"))
+            .mount_exact("/bar", Named("bar"))
+            .mount("/", Named("root"));
+        assert_eq!(mount.len(), 4);
+
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/foo"), "foo []");
+        assert_eq!(body("http://localhost/foo/"), "foo/ []");
+        assert_eq!(body("http://localhost/foo//"), "foo/ []");
+        assert_eq!(body("http://localhost/foo/x"), r#"foo ["x"]"#);
+        assert_eq!(body("http://localhost/bar"), "bar []");
+        assert_eq!(body("http://localhost/bar/"), r#"root ["bar", ""]"#);
+        assert_eq!(body("http://localhost/"), r#"root [""]"#);
+
+        let mut mount = Mount::new();
+        mount.exact_paths(true).mount("/foo/", Named("foo/"));
+        assert_eq!(response_body(dispatch(&mount, "http://localhost/foo/").unwrap()), "foo/ []");
+        assert_eq!(dispatch(&mount, "http://localhost/foo/x").unwrap_err().response.status,
+                   Some(status::NotFound));
+
+        mount.mount_fn("/bar/", |req| {
+            assert_eq!(current_prefix(req), Some(vec!["bar".to_string()]));
+            Ok(mount_aware_redirect(req, "login"))
+        });
+        let res = dispatch(&mount, "http://localhost/bar/").unwrap();
+        assert_eq!(res.headers.get::<headers::Location>().unwrap().0,
+                   "http://localhost:80/bar/login");
+    }
+
+    #[test]
+    fn it_lists_matching_ancestors() {
+        fn path(segments: &[&str]) -> Vec<String> {
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/", send_hello)
+            .mount("/admin", send_hello)
+            .mount_exact("/admin/users", send_hello)
+            .mount("/admin/:section", send_hello)
+            .mount("/admin/users/:id", send_hello)
+            .mount("/other", send_hello);
+
+        assert_eq!(mount.matching_ancestors(&path(&["admin", "users", "7"])),
+                   vec![path(&[]), path(&["admin"]), path(&["admin", ":section"]),
+                        path(&["admin", "users", ":id"])]);
+        assert_eq!(mount.matching_ancestors(&path(&["admin", "users"])),
+                   vec![path(&[]), path(&["admin"]), path(&["admin", ":section"]),
+                        path(&["admin", "users"])]);
+        assert_eq!(Mount::new().matching_ancestors(&path(&["admin"])), Vec::<Vec<String>>::new());
+    }
+
+    #[test]
+    fn it_strips_a_global_prefix() {
+        fn report(req: &mut Request) -> IronResult<Response> {
+            let original = req.extensions.get::<OriginalUrl>().unwrap().path.clone();
+            let prefix = current_prefix(req).unwrap();
+            Ok(Response::with((status::Ok, format!("{:?} {:?} {:?}", original, prefix,
+                                                    req.url.path))))
+        }
+
+        let mut mount = Mount::new();
+        mount.global_prefix("/myapp").mount("/api", report).fallback(Named("fallback"));
+        mount.mount_method(Method::Get, "/items", report);
+
+        let body = |mount: &Mount, url| match dispatch(mount, url) {
+            Ok(res) => response_body(res),
+            Err(err) => format!("{:?}", err.response.status)
+        };
+        assert_eq!(body(&mount, "http://localhost/myapp/api/users"),
+                   r#"["myapp", "api", "users"] ["myapp", "api"] ["users"]"#);
+        assert_eq!(body(&mount, "http://localhost/myapp/other"), r#"fallback ["other"]"#);
+        assert_eq!(body(&mount, "http://localhost/api/users"), "Some(NotFound)");
+        assert_eq!(body(&mount, "http://localhost/myapplication/api"), "Some(NotFound)");
+
+        let path = |segments: &[&str]| segments.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(mount.resolve(&path(&["myapp", "api", "users"])),
+                   Some((path(&["api"]), 2)));
+        assert_eq!(mount.resolve(&path(&["api", "users"])), None);
+        assert_eq!(mount.lookup(&path(&["myapp", "", "api"])).map(|info| info.length), Some(3));
+        assert!(mount.would_match("/myapp/other"));
+        assert!(! mount.would_match("/api/users"));
+        assert_eq!(mount.matching_ancestors(&path(&["myapp", "api", "x"])), vec![path(&["api"])]);
+        assert_eq!(mount.matching_ancestors(&path(&["api", "x"])), Vec::<Vec<String>>::new());
+        assert_eq!(mount.allowed_methods(&path(&["myapp", "items"])), vec![Method::Get]);
+        assert_eq!(mount.allowed_methods(&path(&["items"])), vec![]);
+        assert_eq!(mount.strip_prefix(&path(&["myapp", "api", "x"]), &path(&["api"])),
+                   &path(&["x"])[..]);
+        let base = Url::parse("http://localhost/").unwrap();
+        assert_eq!(mount.url_for("/api", &base).unwrap().to_string(),
+                   "http://localhost:80/myapp/api");
+
+        mount.global_prefix("/");
+        assert_eq!(body(&mount, "http://localhost/api/users"),
+                   r#"["api", "users"] ["api"] ["users"]"#);
+    }
+
+    #[test]
+    fn it_strips_a_forwarded_prefix() {
+        let mut mount = Mount::new();
+        mount.strip_forwarded_prefix(true).mount("/api", Named("api"));
+
+        let body = |url, prefix: Option<&str>| {
+            let res = dispatch_with(&mount, url, |req| if let Some(prefix) = prefix {
+                req.headers.set_raw("X-Forwarded-Prefix", vec![prefix.as_bytes().to_vec()]);
+            });
+            match res {
+                Ok(res) => response_body(res),
+                Err(err) => format!("{:?}", err.response.status)
+            }
+        };
+        assert_eq!(body("http://localhost/api/users", None), r#"api ["users"]"#);
+        assert_eq!(body("http://localhost/myapp/api/users", Some("/myapp/")), r#"api ["users"]"#);
+        assert_eq!(body("http://localhost/api/users", Some("/myapp")), "Some(NotFound)");
+        assert_eq!(body("http://localhost/api/users", Some("/")), r#"api ["users"]"#);
+    }
+
+    #[test]
+    fn it_clones_the_routing_table_but_shares_handlers() {
+        let handler: Arc<Handler> = Arc::new(Named("shared"));
+        let mut mount = Mount::new();
+        mount.mount_arc("/a", handler.clone()).mount("/b", Named("b")).fallback(Named("fallback"));
+
+        let mut copy = mount.clone();
+        assert_eq!(Arc::strong_count(&handler), 3);
+        copy.unmount("/a");
+        copy.mount("/c", Named("c"));
+
+        let body = |mount: &Mount, url| response_body(dispatch(mount, url).unwrap());
+        assert_eq!(body(&mount, "http://localhost/a/x"), r#"shared ["x"]"#);
+        assert_eq!(body(&mount, "http://localhost/c"), r#"fallback ["c"]"#);
+        assert_eq!(body(&copy, "http://localhost/a/x"), r#"fallback ["a", "x"]"#);
+        assert_eq!(body(&copy, "http://localhost/c"), "c []");
+        assert_eq!(body(&copy, "http://localhost/b"), "b []");
+        assert_eq!(Arc::strong_count(&handler), 2);
+    }
+
+    #[test]
+    fn it_redirects_relative_to_the_mount() {
+        let mut inner = Mount::new();
+        inner.mount_fn("/admin", |req| Ok(mount_aware_redirect(req, "/login?next=2")));
+        inner.mount_fn("/home", |req| Ok(mount_aware_redirect(req, "/")));
+        let mut mount = Mount::new();
+        mount.mount("/app", inner);
+
+        let location = |url| {
+            let res = dispatch(&mount, url).unwrap();
+            assert_eq!(res.status, Some(status::Found));
+            res.headers.get::<headers::Location>().unwrap().0.clone()
+        };
+        assert_eq!(location("http://localhost/app/admin/users#top"),
+                   "http://localhost:80/app/admin/login?next=2");
+        assert_eq!(location("http://localhost/app/home?x=1"), "http://localhost:80/app/home/");
+
+        let res = dispatch(&|req: &mut Request| Ok(mount_aware_redirect(req, "a/b")),
+                           "http://localhost/x").unwrap();
+        assert_eq!(res.headers.get::<headers::Location>().unwrap().0, "http://localhost:80/a/b");
+
+        let res = dispatch(&|req: &mut Request| {
+            Ok(mount_aware_redirect(req, "/log in/caf\u{e9}%20bar?q=a b#see this"))
+        }, "http://localhost/x").unwrap();
+        assert_eq!(res.headers.get::<headers::Location>().unwrap().0,
+                   "http://localhost:80/log%20in/caf%C3%A9%20bar?q=a%20b#see%20this");
+    }
+
+    #[test]
+    fn it_mounts_explicit_segments() {
+        let mut mount = Mount::new();
+        mount.mount_segments(&["files", "a/b", "c:"], Named("literal"))
+            .mount_segments(&["users", ":id"], Named("user"))
+            .mount_segments(&[], Named("root"));
+
+        assert_eq!(mount.len(), 3);
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/files/a%2Fb/c:/x"), r#"literal ["x"]"#);
+        assert_eq!(body("http://localhost/users/7"), "user []");
+        assert_eq!(body("http://localhost/files/c:"), r#"root ["files", "c:"]"#);
+    }
+
+    #[test]
+    fn it_parses_routes_as_url_paths() {
+        fn path(segments: &[&str]) -> Vec<String> {
+            segments.iter().map(|s| s.to_string()).collect()
+        }
+
+        let mut mount = Mount::new();
+        mount.mount("/c:/x", send_hello)
+            .mount(r"/a\b", send_hello)
+            .mount("//docs//./v1/../v2/", send_hello);
+
+        let mut routes: Vec<String> = mount.routes().collect();
+        routes.sort();
+        assert_eq!(routes, vec!["/a\\b".to_string(), "/c:/x".to_string(), "/docs/v2".to_string()]);
+        assert_eq!(mount.resolve(&path(&["c:", "x", "y"])), Some((path(&["c:", "x"]), 2)));
+        assert_eq!(mount.resolve(&path(&["a\\b"])), Some((path(&["a\\b"]), 1)));
+    }
+
+    #[test]
+    fn it_merges_routes_under_a_prefix() {
+        let mut feature = Mount::new();
+        feature.mount("/users/:id", Named("user"))
+            .mount_method(Method::Post, "/users", Named("create"))
+            .fallback(Named("fallback"));
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root")).merge_under("/features/accounts", feature);
+
+        assert_eq!(mount.len(), 3);
+        let body = |url| response_body(dispatch(&mount, url).unwrap());
+        assert_eq!(body("http://localhost/features/accounts/users/7/x"), r#"user ["x"]"#);
+        assert_eq!(body("http://localhost/features/accounts/other"),
+                   r#"root ["features", "accounts", "other"]"#);
+
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), "7".to_string());
+        let path: Vec<String> = vec!["features", "accounts", "users", "7", "x"].into_iter()
+            .map(|s| s.to_string()).collect();
+        assert_eq!(mount.lookup(&path).map(|info| (info.length, info.params)), Some((4, params)));
+    }
+
+    #[test]
+    fn it_rejects_paths_deeper_than_the_limit() {
+        let mut mount = Mount::new();
+        mount.max_depth(2).mount("/", Named("root"));
+
+        let status = |mount: &Mount, url| match dispatch(mount, url) {
+            Ok(res) => (None, res.status),
+            Err(err) => (err.error.downcast::<MountError>().cloned(), err.response.status)
+        };
+        assert_eq!(status(&mount, "http://localhost/a/b"), (None, Some(status::Ok)));
+        assert_eq!(status(&mount, "http://localhost//a//b//"), (None, Some(status::Ok)));
+        assert_eq!(status(&mount, "http://localhost/a/b/c"),
+                   (Some(MountError::PathTooLong), Some(status::UriTooLong)));
+
+        mount.limit_response(status::BadRequest);
+        assert_eq!(status(&mount, "http://localhost/a/b/c"),
+                   (Some(MountError::PathTooLong), Some(status::BadRequest)));
+    }
+
+    #[test]
+    fn it_rejects_path_segments_longer_than_the_limit() {
+        let mut mount = Mount::new();
+        mount.mount("/", Named("root"));
+
+        let status = |mount: &Mount, url| match dispatch(mount, url) {
+            Ok(res) => (None, res.status),
+            Err(err) => (err.error.downcast::<MountError>().cloned(), err.response.status)
+        };
+        assert_eq!(status(&mount, "http://localhost/abcdefgh"), (None, Some(status::Ok)));
+
+        mount.max_segment_len(4);
+        assert_eq!(status(&mount, "http://localhost/abcd//efgh/"), (None, Some(status::Ok)));
+        assert_eq!(status(&mount, "http://localhost/abcd/efghi"),
+                   (Some(MountError::SegmentTooLong), Some(status::UriTooLong)));
+        assert_eq!(status(&mount, "http://localhost/%61%62"),
+                   (Some(MountError::SegmentTooLong), Some(status::UriTooLong)));
+    }
+
+    #[test]
+    fn it_dispatches_directly_to_the_root_of_the_outermost_mount() {
+        fn echo_original(req: &mut Request) -> IronResult<Response> {
+            let original = req.extensions.get::<OriginalUrl>().map(|url| url.path.clone());
+            Ok(Response::with((status::Ok, format!("{:?} {:?}", req.url.path, original))))
+        }
+
+        let mut inner = Mount::new();
+        inner.mount("/", echo_original);
+        inner.mount("/api", echo_original);
+
+        let mut mount = Mount::new();
+        mount.mount("/", inner);
+        mount.mount_passthrough("/static", echo_original);
+        let res = dispatch(&mount, "http://localhost/a/b").unwrap();
+        assert_eq!(response_body(res), r#"["a", "b"] None"#);
+        let res = dispatch(&mount, "http://localhost/api/users").unwrap();
+        assert_eq!(response_body(res), r#"["users"] Some(["api", "users"])"#);
+        let res = dispatch(&mount, "http://localhost/static/app.js").unwrap();
+        assert_eq!(response_body(res), r#"["static", "app.js"] Some(["static", "app.js"])"#);
+    }
+
+    fn test_url_matches(mount: &Mount, url: &str) -> bool {
+        dispatch(mount, url).is_ok()
+    }
+
+    // A handler which responds with its name and the path it received.
+    struct Named(&'static str);
+
+    impl Handler for Named {
+        fn handle(&self, req: &mut Request) -> IronResult<Response> {
+            Ok(Response::with((status::Ok, format!("{} {:?}", self.0, req.url.path))))
+        }
+    }
+
+    fn dispatch(handler: &Handler, url: &str) -> IronResult<Response> {
+        dispatch_with(handler, url, |_| ())
+    }
+
+    fn dispatch_with<F>(handler: &Handler, url: &str, setup: F) -> IronResult<Response>
+    where F: FnOnce(&mut Request) {
+        let data = Cursor::new("".to_string().into_bytes());
+        let mut stream = mock::MockStream::new(data);
+        let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+        let mut req = mock::request::new(Method::Get, Url::parse(url).unwrap(),
+            &mut reader);
+        setup(&mut req);
+        handler.handle(&mut req)
+    }
+
+    fn response_body(res: Response) -> String {
+        let mut data = Vec::new();
+        if let Some(mut body) = res.body {
+            body.write_body(&mut ResponseBody::new(&mut data)).unwrap();
+        }
+        String::from_utf8(data).unwrap()
+    }
 
     pub mod mock {
         use hyper::net::NetworkStream;
@@ -0,0 +1,127 @@
+// Counts the allocations made while dispatching through nested mounts. This runs in its own
+// test binary, as it replaces the global allocator.
+
+extern crate hyper;
+extern crate iron;
+extern crate mount;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+
+use hyper::buffer::BufReader;
+use hyper::http::h1::HttpReader;
+use hyper::method::Method;
+use hyper::net::NetworkStream;
+use iron::request::Body;
+use iron::{Handler, Headers, IronResult, Request, Response, TypeMap, Url, headers};
+use mount::Mount;
+
+// A global allocator which counts the allocations made on each thread.
+struct Counting;
+
+thread_local!(static ALLOCATIONS: Cell<usize> = Cell::new(0));
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// Run a function, returning its result and the number of allocations it made.
+fn allocations<F: FnOnce() -> R, R>(f: F) -> (R, usize) {
+    let before = ALLOCATIONS.with(|count| count.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|count| count.get()) - before)
+}
+
+#[derive(Clone)]
+struct MockStream(Cursor<Vec<u8>>);
+
+impl NetworkStream for MockStream {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Ok("127.0.0.1:3000".parse().unwrap())
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Count the allocations made by a handler handling a request for a URL, leaving out those
+// made to build the request.
+fn count_handling(handler: &Handler, url: &str) -> usize {
+    let mut stream = MockStream(Cursor::new(Vec::new()));
+    let mut reader = BufReader::new(&mut stream as &mut NetworkStream);
+    let addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+    let mut headers = Headers::new();
+    headers.set(headers::Host { hostname: "localhost".to_string(), port: Some(3000) });
+    let mut req = Request {
+        method: Method::Get,
+        url: Url::parse(url).unwrap(),
+        body: Body::new(HttpReader::EofReader(&mut reader)),
+        local_addr: addr,
+        remote_addr: addr,
+        headers: headers,
+        extensions: TypeMap::new()
+    };
+
+    let (res, count) = allocations(|| handler.handle(&mut req));
+    assert!(res.is_ok());
+    count
+}
+
+fn send_hello(_: &mut Request) -> IronResult<Response> {
+    Ok(Response::new())
+}
+
+// A chain of mounts, each mounted on one of the routes within the last.
+fn nested(routes: &[&str]) -> Mount {
+    let mut mount = Mount::new();
+    mount.mount(routes[routes.len() - 1], send_hello);
+    for route in routes[..routes.len() - 1].iter().rev() {
+        let mut outer = Mount::new();
+        outer.mount(route, mount);
+        mount = outer;
+    }
+    mount
+}
+
+#[test]
+fn it_does_not_clone_the_url_for_each_nested_mount() {
+    // The cost of each nesting level beyond the second, for a request with a path of a
+    // given length.
+    let per_level = |url: &str| {
+        let two = count_handling(&nested(&["/a", "/b"]), url);
+        let four = count_handling(&nested(&["/a", "/b", "/c", "/d"]), url);
+        (four - two) / 2
+    };
+
+    // Only the outermost mount copies the URL into `OriginalUrl`, and the path is stripped
+    // in place, so what a nested mount allocates doesn't depend on the length of the path
+    // below it.
+    let short = per_level("http://localhost/a/b/c/d/e");
+    let long = per_level("http://localhost/a/b/c/d/e/f/g/h/i/j/k/l/m/n/o/p");
+    assert_eq!(short, long);
+}